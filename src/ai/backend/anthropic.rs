@@ -0,0 +1,239 @@
+use std::ops::ControlFlow;
+
+use crossbeam_channel::Sender;
+use futures::{FutureExt, future::BoxFuture};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{
+    ChatBackend, ChatError, Message, ReceiveMessage, Sampling, check_status, for_each_line,
+    message_json,
+};
+use crate::ai::Role;
+
+const API_VERSION: &str = "2023-06-01";
+/// The Messages API requires an explicit limit on the answer length. This one
+/// is used when the config sets none.
+const MAX_TOKENS: u32 = 4096;
+
+/// A backend for the Anthropic Messages API.
+pub(crate) struct AnthropicBackend {
+    /// The API root, e.g. `https://api.anthropic.com`.
+    pub(crate) base_url: String,
+    pub(crate) api_key: String,
+    pub(crate) model: String,
+    pub(crate) sampling: Sampling,
+}
+
+/// The payload of a streamed event. Its `type` repeats the event name.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event {
+    ContentBlockDelta {
+        delta: Delta,
+    },
+    MessageStop,
+    Error {
+        error: ApiError,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Delta {
+    TextDelta {
+        text: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+impl From<ApiError> for ChatError {
+    fn from(error: ApiError) -> Self {
+        match error.kind.as_str() {
+            "authentication_error" | "permission_error" => ChatError::Auth(error.message),
+            "rate_limit_error" => ChatError::RateLimit(error.message),
+            "api_error" | "overloaded_error" => ChatError::Server(error.message),
+            _ => ChatError::Request(error.message),
+        }
+    }
+}
+
+impl ChatBackend for AnthropicBackend {
+    fn streaming_chat(
+        &self,
+        messages: Vec<Message>,
+        tx: Sender<ReceiveMessage>,
+    ) -> BoxFuture<'static, Result<(), ChatError>> {
+        let url = format!("{}/v1/messages", self.base_url.trim_end_matches('/'));
+        let api_key = self.api_key.clone();
+        let model = self.model.clone();
+        let sampling = self.sampling;
+        async move {
+            // System prompts go into the top-level `system` field, since the
+            // API only accepts user and assistant turns in `messages`.
+            let (system, messages): (Vec<_>, Vec<_>) = messages
+                .iter()
+                .partition(|message| message.role == Role::System);
+            let system = system
+                .iter()
+                .map(|message| message.content.as_str())
+                .collect::<Vec<_>>()
+                .join("\n\n");
+
+            let mut body = json!({
+                "model": model,
+                "max_tokens": MAX_TOKENS,
+                "messages": messages.into_iter().map(message_json).collect::<Vec<_>>(),
+                "stream": true,
+            });
+            sampling.add_to(&mut body, "max_tokens");
+            if !system.is_empty() {
+                body["system"] = system.into();
+            }
+
+            let response = reqwest::Client::new()
+                .post(url)
+                .header("x-api-key", api_key)
+                .header("anthropic-version", API_VERSION)
+                .json(&body)
+                .send()
+                .await?;
+            let mut response = check_status(response).await?;
+
+            for_each_line(&mut response, |line| {
+                let Some(data) = line.strip_prefix("data:") else {
+                    return Ok(ControlFlow::Continue(()));
+                };
+                match serde_json::from_str::<Event>(data.trim())? {
+                    Event::ContentBlockDelta {
+                        delta: Delta::TextDelta { text },
+                    } => {
+                        if !text.is_empty() {
+                            let _ = tx.send(ReceiveMessage::Content(text));
+                        }
+                    }
+                    Event::MessageStop => return Ok(ControlFlow::Break(())),
+                    Event::Error { error } => return Err(error.into()),
+                    _ => {}
+                }
+                Ok(ControlFlow::Continue(()))
+            })
+            .await
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{super::mock, *};
+
+    fn backend(base_url: String) -> AnthropicBackend {
+        AnthropicBackend {
+            base_url,
+            api_key: "secret".to_string(),
+            model: "test-model".to_string(),
+            sampling: Sampling {
+                temperature: None,
+                top_p: None,
+                max_tokens: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_text_until_message_stop() {
+        let (url, server) = mock::serve(&[
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+            "event: ping\ndata: {\"type\":\"ping\"}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hel\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"lo\"}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"!\"}}\n\n",
+        ])
+        .await;
+        let messages = vec![
+            Message::system("Be brief."),
+            Message::user("Hi"),
+            Message::assistant("Hello!"),
+            Message::user("Again"),
+        ];
+
+        let (result, content) = mock::chat(&backend(url), messages).await;
+        let request = server.await.unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(content, "Hello");
+        assert_eq!(request.request_line, "POST /v1/messages HTTP/1.1");
+        assert_eq!(request.header("x-api-key"), Some("secret"));
+        assert_eq!(request.body["system"], "Be brief.");
+        assert_eq!(
+            request.body["messages"],
+            json!([
+                { "role": "user", "content": "Hi" },
+                { "role": "assistant", "content": "Hello!" },
+                { "role": "user", "content": "Again" },
+            ])
+        );
+        assert_eq!(request.body["max_tokens"], MAX_TOKENS);
+    }
+
+    #[tokio::test]
+    async fn sends_the_configured_max_tokens() {
+        let (url, server) = mock::serve(&["data: {\"type\":\"message_stop\"}\n\n"]).await;
+        let mut backend = backend(url);
+        backend.sampling.max_tokens = Some(100);
+
+        let (result, _) = mock::chat(&backend, vec![Message::user("Hi")]).await;
+        let request = server.await.unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(request.body["max_tokens"], 100);
+        assert!(request.body.get("system").is_none());
+    }
+
+    #[tokio::test]
+    async fn turns_error_events_into_chat_errors() {
+        let (url, server) = mock::serve(&[
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n",
+            "event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n",
+        ])
+        .await;
+
+        let (result, content) = mock::chat(&backend(url), vec![Message::user("Hi")]).await;
+        server.await.unwrap();
+
+        assert!(matches!(result, Err(ChatError::Server(message)) if message == "Overloaded"));
+        assert_eq!(content, "Hi");
+    }
+
+    #[test]
+    fn maps_error_types() {
+        let error = |kind: &str| {
+            ChatError::from(ApiError {
+                kind: kind.to_string(),
+                message: String::new(),
+            })
+        };
+
+        assert!(matches!(error("authentication_error"), ChatError::Auth(_)));
+        assert!(matches!(error("rate_limit_error"), ChatError::RateLimit(_)));
+        assert!(matches!(
+            error("invalid_request_error"),
+            ChatError::Request(_)
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use crossbeam_channel::Receiver;
+use serde_json::Value;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    task::JoinHandle,
+};
+
+use super::{ChatBackend, ChatError, Message, ReceiveMessage};
+
+/// A request received by [`serve`].
+pub(super) struct RecordedRequest {
+    /// The request line, e.g. `POST /v1/chat/completions HTTP/1.1`.
+    pub(super) request_line: String,
+    /// The headers, with lowercase names.
+    pub(super) headers: Vec<(String, String)>,
+    pub(super) body: Value,
+}
+
+impl RecordedRequest {
+    pub(super) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(other, _)| other == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Serves a single request on 127.0.0.1, answering with `chunks` written one
+/// at a time so that they arrive as separate reads. Returns the base URL and
+/// the request once it is served.
+pub(super) async fn serve(chunks: &[&str]) -> (String, JoinHandle<RecordedRequest>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let chunks: Vec<String> = chunks.iter().map(|chunk| chunk.to_string()).collect();
+
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+
+        let mut buffer = Vec::new();
+        let header_end = loop {
+            if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+                break end;
+            }
+            let mut bytes = [0; 4096];
+            let count = stream.read(&mut bytes).await.unwrap();
+            assert!(count > 0, "the request ended before its headers");
+            buffer.extend_from_slice(&bytes[..count]);
+        };
+        let head = String::from_utf8(buffer[..header_end].to_vec()).unwrap();
+        let mut lines = head.split("\r\n");
+        let request_line = lines.next().unwrap().to_string();
+        let headers: Vec<_> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+            .collect();
+        let length: usize = headers
+            .iter()
+            .find(|(name, _)| name == "content-length")
+            .map_or(0, |(_, value)| value.parse().unwrap());
+        let mut body = buffer[header_end + 4..].to_vec();
+        while body.len() < length {
+            let mut bytes = [0; 4096];
+            let count = stream.read(&mut bytes).await.unwrap();
+            assert!(count > 0, "the request ended before its body");
+            body.extend_from_slice(&bytes[..count]);
+        }
+
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        // The client may hang up early, e.g. on a malformed event.
+        for chunk in chunks {
+            if stream.write_all(chunk.as_bytes()).await.is_err() {
+                break;
+            }
+            let _ = stream.flush().await;
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let _ = stream.shutdown().await;
+
+        RecordedRequest {
+            request_line,
+            headers,
+            body: serde_json::from_slice(&body).unwrap(),
+        }
+    });
+    (url, server)
+}
+
+/// Runs `backend` on `messages`, returning the result and the streamed
+/// content.
+pub(super) async fn chat(
+    backend: &dyn ChatBackend,
+    messages: Vec<Message>,
+) -> (Result<(), ChatError>, String) {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let result = backend.streaming_chat(messages, tx).await;
+    (result, content(&rx))
+}
+
+fn content(rx: &Receiver<ReceiveMessage>) -> String {
+    rx.try_iter()
+        .map(|message| match message {
+            ReceiveMessage::Content(content) => content,
+            _ => String::new(),
+        })
+        .collect()
+}
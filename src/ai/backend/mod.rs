@@ -0,0 +1,220 @@
+mod anthropic;
+#[cfg(test)]
+mod mock;
+mod ollama;
+mod openai;
+
+use std::ops::ControlFlow;
+
+use crossbeam_channel::Sender;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::{ChatError, Config, Message, ReceiveMessage};
+use anthropic::AnthropicBackend;
+use ollama::OllamaBackend;
+use openai::OpenAiBackend;
+
+/// A chat model provider.
+pub(crate) trait ChatBackend: Send + Sync {
+    /// Requests an answer to `messages`, sending every piece of content to `tx`
+    /// as a [`ReceiveMessage::Content`] while it streams in.
+    fn streaming_chat(
+        &self,
+        messages: Vec<Message>,
+        tx: Sender<ReceiveMessage>,
+    ) -> BoxFuture<'static, Result<(), ChatError>>;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) enum Provider {
+    #[default]
+    DeepSeek,
+    /// Any server implementing the OpenAI chat completions API.
+    OpenAiCompatible,
+    /// A local Ollama server, using its native API.
+    Ollama,
+    /// The Anthropic Messages API.
+    Anthropic,
+}
+
+impl Provider {
+    pub(crate) const ALL: [Provider; 4] = [
+        Provider::DeepSeek,
+        Provider::OpenAiCompatible,
+        Provider::Ollama,
+        Provider::Anthropic,
+    ];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Provider::DeepSeek => "DeepSeek",
+            Provider::OpenAiCompatible => "OpenAI-compatible",
+            Provider::Ollama => "Ollama",
+            Provider::Anthropic => "Anthropic",
+        }
+    }
+
+    /// Whether requests are refused without an API key.
+    pub(crate) fn needs_api_key(self) -> bool {
+        matches!(self, Provider::DeepSeek | Provider::Anthropic)
+    }
+
+    pub(crate) fn default_base_url(self) -> &'static str {
+        match self {
+            Provider::DeepSeek => "https://api.deepseek.com",
+            Provider::OpenAiCompatible => "http://localhost:8000/v1",
+            Provider::Ollama => "http://localhost:11434",
+            Provider::Anthropic => "https://api.anthropic.com",
+        }
+    }
+
+    /// The model used when the config names none, `None` if the model must
+    /// be named because servers differ in what they serve.
+    pub(crate) fn default_model(self) -> Option<&'static str> {
+        match self {
+            Provider::DeepSeek => Some("deepseek-chat"),
+            Provider::OpenAiCompatible => None,
+            Provider::Ollama => Some("llama3.2"),
+            Provider::Anthropic => Some("claude-3-5-haiku-latest"),
+        }
+    }
+}
+
+/// The sampling parameters of a request, each left to the provider when not
+/// set.
+#[derive(Clone, Copy)]
+pub(crate) struct Sampling {
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    max_tokens: Option<u32>,
+}
+
+impl Sampling {
+    /// Adds the parameters that are set to the JSON object `target`, naming
+    /// the answer length `max_tokens_key`.
+    fn add_to(self, target: &mut Value, max_tokens_key: &str) {
+        if let Some(temperature) = self.temperature {
+            target["temperature"] = temperature.into();
+        }
+        if let Some(top_p) = self.top_p {
+            target["top_p"] = top_p.into();
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            target[max_tokens_key] = max_tokens.into();
+        }
+    }
+}
+
+/// The model `config` asks for answers, `None` if it names none and the
+/// provider has no default.
+pub(crate) fn model_name(config: &Config) -> Option<&str> {
+    if config.model.is_empty() {
+        config.provider.default_model()
+    } else {
+        Some(&config.model)
+    }
+}
+
+/// Creates the backend selected by `config`.
+pub(crate) fn from_config(config: &Config) -> Result<Box<dyn ChatBackend>, ChatError> {
+    let provider = config.provider;
+    let base_url = if config.base_url.is_empty() {
+        provider.default_base_url()
+    } else {
+        &config.base_url
+    }
+    .to_string();
+    let Some(model) = model_name(config) else {
+        return Err(ChatError::Config(format!(
+            "{} servers need a model name. Set one in the settings.",
+            provider.label()
+        )));
+    };
+    let model = model.to_string();
+    let sampling = Sampling {
+        temperature: config.temperature,
+        top_p: config.top_p,
+        max_tokens: config.max_tokens,
+    };
+
+    Ok(match provider {
+        Provider::DeepSeek | Provider::OpenAiCompatible => Box::new(OpenAiBackend {
+            base_url,
+            api_key: config.api_key.clone(),
+            model,
+            sampling,
+        }),
+        Provider::Ollama => Box::new(OllamaBackend {
+            base_url,
+            model,
+            sampling,
+        }),
+        Provider::Anthropic => Box::new(AnthropicBackend {
+            base_url,
+            api_key: config.api_key.clone(),
+            model,
+            sampling,
+        }),
+    })
+}
+
+/// The `{ role, content }` object shared by the OpenAI, Ollama and Anthropic
+/// APIs.
+fn message_json(message: &Message) -> Value {
+    json!({ "role": message.role.as_str(), "content": message.content })
+}
+
+/// Turns an unsuccessful response into a [`ChatError`], using the error
+/// message from the body when the provider sends one.
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, ChatError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let text = response.text().await.unwrap_or_default();
+    Err(ChatError::from_status(status, error_message(&text)))
+}
+
+/// Extracts the message from the error bodies used by the supported APIs:
+/// `{ "error": { "message": ... } }` or `{ "error": ... }`.
+fn error_message(body: &str) -> String {
+    let Ok(value) = serde_json::from_str::<Value>(body) else {
+        return body.trim().to_string();
+    };
+    let error = &value["error"];
+    error["message"]
+        .as_str()
+        .or(error.as_str())
+        .unwrap_or(body.trim())
+        .to_string()
+}
+
+/// Reads the response body line by line, calling `f` with each line until it
+/// breaks.
+///
+/// Streamed lines may be split across chunks, so the body is buffered until a
+/// full line is available. A body ending before `f` breaks means the
+/// connection dropped mid-answer.
+async fn for_each_line(
+    response: &mut reqwest::Response,
+    mut f: impl FnMut(&str) -> Result<ControlFlow<()>, ChatError>,
+) -> Result<(), ChatError> {
+    let mut buffer = Vec::new();
+    while let Some(bytes) = response.chunk().await? {
+        buffer.extend_from_slice(&bytes);
+        while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            if f(String::from_utf8_lossy(&line).trim())?.is_break() {
+                return Ok(());
+            }
+        }
+    }
+    if !buffer.is_empty() && f(String::from_utf8_lossy(&buffer).trim())?.is_break() {
+        return Ok(());
+    }
+    Err(ChatError::Network(
+        "The connection closed before the answer was complete.".to_string(),
+    ))
+}
@@ -0,0 +1,106 @@
+use std::ops::ControlFlow;
+
+use crossbeam_channel::Sender;
+use futures::{FutureExt, future::BoxFuture};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{
+    ChatBackend, ChatError, Message, ReceiveMessage, Sampling, check_status, for_each_line,
+    message_json,
+};
+
+/// A backend for Ollama's native `/api/chat` endpoint, which streams
+/// newline-delimited JSON.
+pub(crate) struct OllamaBackend {
+    /// The server root, e.g. `http://localhost:11434`.
+    pub(crate) base_url: String,
+    pub(crate) model: String,
+    pub(crate) sampling: Sampling,
+}
+
+#[derive(Deserialize)]
+struct Chunk {
+    message: Option<ChunkMessage>,
+    #[serde(default)]
+    done: bool,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChunkMessage {
+    content: String,
+}
+
+impl ChatBackend for OllamaBackend {
+    fn streaming_chat(
+        &self,
+        messages: Vec<Message>,
+        tx: Sender<ReceiveMessage>,
+    ) -> BoxFuture<'static, Result<(), ChatError>> {
+        let base_url = self.base_url.trim_end_matches('/').to_string();
+        let model = self.model.clone();
+        let sampling = self.sampling;
+        async move {
+            let mut body = json!({
+                "model": model,
+                "messages": messages.iter().map(message_json).collect::<Vec<_>>(),
+                "stream": true,
+            });
+            // Ollama takes sampling parameters as model options.
+            let mut options = json!({});
+            sampling.add_to(&mut options, "num_predict");
+            if options != json!({}) {
+                body["options"] = options;
+            }
+
+            let response = reqwest::Client::new()
+                .post(format!("{base_url}/api/chat"))
+                .json(&body)
+                .send()
+                .await;
+            let response = match response {
+                Ok(response) => response,
+                Err(err) if err.is_connect() => {
+                    return Err(ChatError::Network(format!(
+                        "Could not connect to Ollama at {base_url}. Is it running?"
+                    )));
+                }
+                Err(err) => return Err(err.into()),
+            };
+            // Ollama answers 404 when the model hasn't been pulled.
+            let status = response.status();
+            let mut response = check_status(response).await.map_err(|err| match err {
+                ChatError::Request(reason) if status == StatusCode::NOT_FOUND => {
+                    ChatError::Request(format!(
+                        "Model \"{model}\" was not found. Pull it with `ollama pull {model}`. ({reason})"
+                    ))
+                }
+                err => err,
+            })?;
+
+            for_each_line(&mut response, |line| {
+                if line.is_empty() {
+                    return Ok(ControlFlow::Continue(()));
+                }
+                let chunk: Chunk = serde_json::from_str(line)?;
+                if let Some(error) = chunk.error {
+                    return Err(ChatError::Server(error));
+                }
+                if let Some(message) = chunk.message
+                    && !message.content.is_empty()
+                {
+                    let _ = tx.send(ReceiveMessage::Content(message.content));
+                }
+                Ok(if chunk.done {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                })
+            })
+            .await
+        }
+        .boxed()
+    }
+}
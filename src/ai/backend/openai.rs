@@ -0,0 +1,217 @@
+use std::ops::ControlFlow;
+
+use crossbeam_channel::Sender;
+use futures::{FutureExt, future::BoxFuture};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{
+    ChatBackend, ChatError, Message, ReceiveMessage, Sampling, check_status, for_each_line,
+    message_json,
+};
+
+/// A backend for the OpenAI-compatible `/chat/completions` endpoint, served
+/// by DeepSeek, vLLM, llama.cpp server, Ollama and others.
+pub(crate) struct OpenAiBackend {
+    /// The API root, e.g. `http://localhost:8000/v1`.
+    pub(crate) base_url: String,
+    pub(crate) api_key: String,
+    pub(crate) model: String,
+    pub(crate) sampling: Sampling,
+}
+
+#[derive(Deserialize)]
+struct Chunk {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    delta: ChunkDelta,
+}
+
+#[derive(Deserialize)]
+struct ChunkDelta {
+    content: Option<String>,
+}
+
+impl ChatBackend for OpenAiBackend {
+    fn streaming_chat(
+        &self,
+        messages: Vec<Message>,
+        tx: Sender<ReceiveMessage>,
+    ) -> BoxFuture<'static, Result<(), ChatError>> {
+        let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
+        let api_key = self.api_key.clone();
+        let model = self.model.clone();
+        let sampling = self.sampling;
+        async move {
+            let mut body = json!({
+                "model": model,
+                "messages": messages.iter().map(message_json).collect::<Vec<_>>(),
+                "stream": true,
+            });
+            sampling.add_to(&mut body, "max_tokens");
+
+            let mut request = reqwest::Client::new().post(url).json(&body);
+            // Local servers usually don't check the key at all.
+            if !api_key.is_empty() {
+                request = request.bearer_auth(api_key);
+            }
+            let mut response = check_status(request.send().await?).await?;
+
+            for_each_line(&mut response, |line| {
+                let Some(data) = line.strip_prefix("data:") else {
+                    return Ok(ControlFlow::Continue(()));
+                };
+                let data = data.trim();
+                if data == "[DONE]" {
+                    return Ok(ControlFlow::Break(()));
+                }
+                let chunk: Chunk = serde_json::from_str(data)?;
+                for content in chunk.choices.into_iter().filter_map(|c| c.delta.content) {
+                    if !content.is_empty() {
+                        let _ = tx.send(ReceiveMessage::Content(content));
+                    }
+                }
+                Ok(ControlFlow::Continue(()))
+            })
+            .await
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        super::{Provider, Sampling, from_config, mock},
+        *,
+    };
+    use crate::ai::Config;
+
+    fn backend(base_url: String, api_key: &str) -> OpenAiBackend {
+        OpenAiBackend {
+            base_url,
+            api_key: api_key.to_string(),
+            model: "test-model".to_string(),
+            sampling: Sampling {
+                temperature: None,
+                top_p: None,
+                max_tokens: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_the_whole_dialog_to_the_configured_server() {
+        let (url, server) = mock::serve(&[
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n",
+            "data: [DONE]\n\n",
+        ])
+        .await;
+        let config = Config {
+            provider: Provider::OpenAiCompatible,
+            base_url: format!("{url}/v1"),
+            model: "test-model".to_string(),
+            ..Default::default()
+        };
+        let messages = vec![
+            Message::system("Be brief."),
+            Message::user("Hi"),
+            Message::assistant("Hello!"),
+            Message::user("How are you?"),
+        ];
+
+        let (result, content) = mock::chat(&*from_config(&config).unwrap(), messages).await;
+        let request = server.await.unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(content, "Hello");
+        assert_eq!(request.request_line, "POST /v1/chat/completions HTTP/1.1");
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        assert_eq!(request.body["model"], "test-model");
+        assert_eq!(
+            request.body["messages"],
+            json!([
+                { "role": "system", "content": "Be brief." },
+                { "role": "user", "content": "Hi" },
+                { "role": "assistant", "content": "Hello!" },
+                { "role": "user", "content": "How are you?" },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn joins_events_split_across_chunks() {
+        let (url, server) = mock::serve(&[
+            "data: {\"choices\":[{\"del",
+            "ta\":{\"content\":\"Hi\"}}]}\n",
+            "\ndata: {\"choices\":[{\"delta\":{\"content\":\" there\"}}]}\n\ndata: [DONE]\n\n",
+        ])
+        .await;
+
+        let (result, content) = mock::chat(&backend(url, ""), vec![Message::user("Hi")]).await;
+        server.await.unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(content, "Hi there");
+    }
+
+    #[tokio::test]
+    async fn stops_at_done() {
+        let (url, server) = mock::serve(&[
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\n",
+            "data: [DONE]\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\" again\"}}]}\n\n",
+        ])
+        .await;
+
+        let (result, content) = mock::chat(&backend(url, ""), vec![Message::user("Hi")]).await;
+        server.await.unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(content, "Hi");
+    }
+
+    #[tokio::test]
+    async fn reports_malformed_data() {
+        let (url, server) = mock::serve(&[
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\n",
+            "data: {\"choices\":\n\n",
+        ])
+        .await;
+
+        let (result, content) = mock::chat(&backend(url, ""), vec![Message::user("Hi")]).await;
+        server.await.unwrap();
+
+        assert!(matches!(result, Err(ChatError::MalformedStream(_))));
+        assert_eq!(content, "Hi");
+    }
+
+    #[tokio::test]
+    async fn sends_the_api_key_only_when_set() {
+        for (api_key, authorization) in [("", None), ("secret", Some("Bearer secret"))] {
+            let (url, server) = mock::serve(&["data: [DONE]\n\n"]).await;
+
+            let (result, _) = mock::chat(&backend(url, api_key), vec![Message::user("Hi")]).await;
+            let request = server.await.unwrap();
+
+            assert!(result.is_ok());
+            assert_eq!(request.header("authorization"), authorization);
+        }
+    }
+
+    #[test]
+    fn requires_a_model_for_openai_compatible_servers() {
+        let config = Config {
+            provider: Provider::OpenAiCompatible,
+            ..Default::default()
+        };
+
+        assert!(matches!(from_config(&config), Err(ChatError::Config(_))));
+    }
+}
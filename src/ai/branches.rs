@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+
+use super::{
+    Config, Dialog, Role,
+    chat::{StreamReceiver, TokioRuntime, cancel_chat, cancel_chat_if_left, start_chat},
+};
+
+/// Adds a new version of the message at `index` in its own branch and asks
+/// the model again from there.
+#[derive(Message, Clone)]
+pub(crate) struct EditMessage {
+    pub(crate) index: usize,
+    pub(crate) content: String,
+}
+
+/// Asks the model again from the message at this index. The answer is kept
+/// next to the old one if it is an answer, or else added as another reply to
+/// it.
+#[derive(Message, Clone)]
+pub(crate) struct Regenerate(pub(crate) usize);
+
+/// Removes the message at this index from the dialog.
+#[derive(Message, Clone)]
+pub(crate) struct DeleteMessage(pub(crate) usize);
+
+/// Shows the branch through the message at this index.
+#[derive(Message, Clone)]
+pub(crate) struct SelectBranch(pub(crate) usize);
+
+/// Another branch of the [`Dialog`] is shown.
+#[derive(Message, Clone)]
+pub(crate) struct BranchSwitched;
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn on_branch_action(
+    mut commands: Commands,
+    mut edit_message: MessageReader<EditMessage>,
+    mut regenerate: MessageReader<Regenerate>,
+    mut delete_message: MessageReader<DeleteMessage>,
+    mut select_branch: MessageReader<SelectBranch>,
+    mut branch_switched: MessageWriter<BranchSwitched>,
+    mut dialog: ResMut<Dialog>,
+    mut stream_receiver: Option<ResMut<StreamReceiver>>,
+    config: Res<Config>,
+    tokio_runtime: Res<TokioRuntime>,
+) {
+    let edit = edit_message.read().last();
+    let regenerated = regenerate.read().last();
+    let selected = select_branch.read().last();
+    let deleted = delete_message.read().last();
+    if edit.is_none() && regenerated.is_none() && selected.is_none() && deleted.is_none() {
+        return;
+    }
+
+    // A new answer replaces the one being streamed, which continues the
+    // branch that is left.
+    if edit.is_some() || regenerated.is_some() {
+        cancel_chat(&mut dialog, &mut stream_receiver, &mut commands);
+    }
+    if let Some(SelectBranch(index)) = selected {
+        dialog.select(*index);
+        cancel_chat_if_left(&mut dialog, &mut stream_receiver, &mut commands);
+    }
+    if let Some(EditMessage { index, content }) = edit {
+        dialog.edit(*index, content);
+        start_chat(&dialog, &config, &tokio_runtime, &mut commands);
+    } else if let Some(Regenerate(index)) = regenerated {
+        match dialog.message(*index).role {
+            Role::Assistant => dialog.rewind(*index),
+            Role::System | Role::User => dialog.end_at(*index),
+        }
+        start_chat(&dialog, &config, &tokio_runtime, &mut commands);
+    }
+    // Removing a message shifts the indices after it, so it comes last.
+    if let Some(DeleteMessage(index)) = deleted {
+        // The answer can't follow a message that is gone.
+        if stream_receiver
+            .as_ref()
+            .is_some_and(|stream_receiver| stream_receiver.follows(&dialog, *index))
+        {
+            cancel_chat(&mut dialog, &mut stream_receiver, &mut commands);
+        }
+        dialog.remove(*index);
+        if let Some(stream_receiver) = &mut stream_receiver {
+            stream_receiver.message_removed(*index);
+        }
+    }
+    if let Err(err) = dialog.save() {
+        log::error!("Failed to save dialog: {err}");
+    }
+    branch_switched.write(BranchSwitched);
+}
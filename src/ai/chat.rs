@@ -1,82 +1,267 @@
+use std::collections::{HashMap, VecDeque};
+
 use bevy::prelude::*;
 use crossbeam_channel::Receiver;
-use futures::pin_mut;
+use futures::{FutureExt, future};
+use tokio::task::AbortHandle;
 
-use super::{Config, Dialog};
-use deepseek_api::AsyncIteratorNext;
-use deepseek_api::{Delta, Model};
+use super::{ChatError, Config, Dialog, Message, backend};
 
 #[derive(Resource, Deref)]
 pub(crate) struct TokioRuntime(pub(crate) tokio::runtime::Runtime);
 
 #[derive(Resource)]
-pub(crate) struct StreamReceiver(Receiver<ReceiveMessage>);
+pub(crate) struct StreamReceiver {
+    receiver: Receiver<ReceiveMessage>,
+    /// The message the answer follows, which ends the shown branch while the
+    /// answer streams.
+    parent: Option<usize>,
+    /// The assistant answer received so far.
+    answer: String,
+    /// The model writing the answer.
+    model: String,
+    /// Aborts the task streaming the answer.
+    task: AbortHandle,
+}
 
 #[derive(Message, Clone, Deref)]
 pub(crate) struct SendMessage(pub(crate) String);
 
+/// Messages waiting for the current answer to finish, oldest first, by the
+/// id of their conversation. They are sent while it is open.
+#[derive(Resource, Default)]
+pub(crate) struct PendingMessages(HashMap<String, VecDeque<String>>);
+
+/// The oldest pending message was sent to the model and added to the dialog
+/// at this index.
+#[derive(Message, Clone)]
+pub(crate) struct MessageSent(pub(crate) usize);
+
+/// The finished or stopped answer was added to the dialog at this index.
+#[derive(Message, Clone)]
+pub(crate) struct AnswerAdded(pub(crate) usize);
+
+/// Stops the answer currently being streamed, keeping what has arrived so far.
+#[derive(Message, Clone)]
+pub(crate) struct StopGeneration;
+
 #[derive(Message, Clone)]
 pub(crate) enum ReceiveMessage {
     Content(String),
+    /// The request failed; sent right before [`ReceiveMessage::Finished`].
+    Error(ChatError),
+    /// The answer was stopped by the user; sent instead of
+    /// [`ReceiveMessage::Finished`].
+    Interrupted,
     Finished,
 }
 
+impl StreamReceiver {
+    /// Keeps following the same message after a system prompt was inserted
+    /// before all the others.
+    pub(super) fn system_prompt_inserted(&mut self) {
+        self.parent = Some(self.parent.map_or(0, |parent| parent + 1));
+    }
+
+    /// Whether the answer follows the message at `index`, directly or through
+    /// other messages.
+    pub(super) fn follows(&self, dialog: &Dialog, index: usize) -> bool {
+        dialog.follows(self.parent, index)
+    }
+
+    /// Keeps following the same message after an unrelated one was removed.
+    pub(super) fn message_removed(&mut self, index: usize) {
+        self.parent = self
+            .parent
+            .map(|parent| if parent > index { parent - 1 } else { parent });
+    }
+
+    /// Stops the answer and returns what has arrived so far, if anything.
+    pub(super) fn interrupt(&mut self) -> Option<Message> {
+        self.task.abort();
+        for chunk in self.receiver.try_iter() {
+            if let ReceiveMessage::Content(content) = chunk {
+                self.answer.push_str(&content);
+            }
+        }
+        (!self.answer.is_empty()).then(|| {
+            let mut message = Message::assistant(&self.answer);
+            message.interrupted = true;
+            message.model = Some(self.model.clone());
+            message
+        })
+    }
+}
+
+impl PendingMessages {
+    /// The messages waiting in the conversation with this id, oldest first.
+    pub(crate) fn of(&self, id: &str) -> impl Iterator<Item = &str> {
+        self.0.get(id).into_iter().flatten().map(String::as_str)
+    }
+
+    /// Drops the messages of a deleted conversation.
+    pub(super) fn remove(&mut self, id: &str) {
+        self.0.remove(id);
+    }
+}
+
+/// Adds `answer` after the message at `parent`, continuing the shown branch
+/// if it still ends there, and returns its index.
+fn add_answer(dialog: &mut Dialog, parent: Option<usize>, answer: Message) -> usize {
+    let index = dialog.add_reply(parent, answer);
+    if dialog.current() == parent {
+        dialog.end_at(index);
+    }
+    index
+}
+
+/// Stops the answer being streamed, if any, adding what has arrived to
+/// `dialog`. Returns whether an answer was being streamed.
+pub(super) fn cancel_chat(
+    dialog: &mut Dialog,
+    stream_receiver: &mut Option<ResMut<StreamReceiver>>,
+    commands: &mut Commands,
+) -> bool {
+    let Some(mut stream_receiver) = stream_receiver.take() else {
+        return false;
+    };
+    if let Some(answer) = stream_receiver.interrupt() {
+        add_answer(dialog, stream_receiver.parent, answer);
+    }
+    commands.remove_resource::<StreamReceiver>();
+    true
+}
+
+/// Stops the answer being streamed if the shown branch no longer ends with
+/// the message it follows. What has arrived is kept in the branch that was
+/// left.
+pub(super) fn cancel_chat_if_left(
+    dialog: &mut Dialog,
+    stream_receiver: &mut Option<ResMut<StreamReceiver>>,
+    commands: &mut Commands,
+) {
+    if stream_receiver
+        .as_ref()
+        .is_some_and(|stream_receiver| stream_receiver.parent != dialog.current())
+    {
+        cancel_chat(dialog, stream_receiver, commands);
+    }
+}
+
+/// Starts streaming the answer to the shown branch of `dialog`.
+pub(super) fn start_chat(
+    dialog: &Dialog,
+    config: &Config,
+    tokio_runtime: &TokioRuntime,
+    commands: &mut Commands,
+) {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let chat = match backend::from_config(config) {
+        Ok(backend) => backend.streaming_chat(dialog.messages().cloned().collect(), tx.clone()),
+        Err(err) => future::ready(Err(err)).boxed(),
+    };
+
+    let task = tokio_runtime.spawn(async move {
+        if let Err(err) = chat.await {
+            log::error!("Chat request failed: {err}");
+            let _ = tx.send(ReceiveMessage::Error(err));
+        }
+        tx.send(ReceiveMessage::Finished)
+    });
+    commands.insert_resource(StreamReceiver {
+        receiver: rx,
+        parent: dialog.current(),
+        answer: String::new(),
+        model: backend::model_name(config).unwrap_or_default().to_string(),
+        task: task.abort_handle(),
+    });
+}
+
 impl SendMessage {
     pub(crate) fn new(message: &str) -> Self {
         Self(message.to_string())
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn on_send_message(
     mut commands: Commands,
     mut send_message: MessageReader<SendMessage>,
+    mut message_sent: MessageWriter<MessageSent>,
+    mut pending_messages: ResMut<PendingMessages>,
     mut dialog: ResMut<Dialog>,
     tokio_runtime: Res<TokioRuntime>,
     config: Res<Config>,
     stream_receiver: Option<ResMut<StreamReceiver>>,
 ) {
+    let pending_messages = pending_messages.0.entry(dialog.id.clone()).or_default();
+    pending_messages.extend(send_message.read().map(|message| message.0.clone()));
+
     let is_chatting = stream_receiver.is_some();
     if is_chatting {
         return;
     }
-    if let Some(message) = send_message.read().next() {
-        let message = message.clone();
-        let mut client = deepseek_api::Client::new(Model::DeepSeekChat, &config.api_key);
-
-        dialog
-            .0
-            .push(deepseek_api::message::Message::user(&message));
-        let (tx, rx) = crossbeam_channel::unbounded();
-        commands.insert_resource(StreamReceiver(rx));
-
-        tokio_runtime.spawn(async move {
-            let stream = client.streaming_chat(&message).await;
-            pin_mut!(stream);
-            while let Some(delta) = stream.next().await {
-                match delta {
-                    Delta::Content { content, .. } => {
-                        tx.send(ReceiveMessage::Content(content)).unwrap()
-                    }
-                    _ => unreachable!(),
-                }
-            }
-            tx.send(ReceiveMessage::Finished)
-        });
+    if let Some(message) = pending_messages.pop_front() {
+        let index = dialog.push(Message::user(&message));
+        message_sent.write(MessageSent(index));
+        start_chat(&dialog, &config, &tokio_runtime, &mut commands);
     }
 }
 
 pub(crate) fn read_stream(
     mut commands: Commands,
     stream_receiver: Option<ResMut<StreamReceiver>>,
+    mut stop_generation: MessageReader<StopGeneration>,
+    mut dialog: ResMut<Dialog>,
     mut receive_message: MessageWriter<ReceiveMessage>,
+    mut answer_added: MessageWriter<AnswerAdded>,
 ) {
-    if let Some(receiver) = stream_receiver {
-        for chunk in receiver.0.try_iter() {
-            receive_message.write(chunk.clone());
-            if let ReceiveMessage::Finished = chunk {
-                commands.remove_resource::<StreamReceiver>();
+    let stop_requested = !stop_generation.is_empty();
+    stop_generation.clear();
+
+    let Some(mut stream_receiver) = stream_receiver else {
+        return;
+    };
+    let StreamReceiver {
+        receiver,
+        parent,
+        answer,
+        model,
+        task,
+    } = &mut *stream_receiver;
+    if stop_requested {
+        task.abort();
+    }
+
+    // Chunks sent before the task was aborted are still kept.
+    let mut is_finished = false;
+    let mut is_interrupted = false;
+    for chunk in receiver.try_iter() {
+        receive_message.write(chunk.clone());
+        match chunk {
+            ReceiveMessage::Content(content) => answer.push_str(&content),
+            ReceiveMessage::Error(_) | ReceiveMessage::Interrupted => {}
+            ReceiveMessage::Finished => {
+                is_finished = true;
                 break;
             }
         }
     }
+    if stop_requested && !is_finished {
+        receive_message.write(ReceiveMessage::Interrupted);
+        is_interrupted = true;
+    }
+
+    if is_finished || is_interrupted {
+        if !answer.is_empty() {
+            let mut message = Message::assistant(answer);
+            message.interrupted = is_interrupted;
+            message.model = Some(model.clone());
+            answer_added.write(AnswerAdded(add_answer(&mut dialog, *parent, message)));
+        }
+        if let Err(err) = dialog.save() {
+            log::error!("Failed to save dialog: {err}");
+        }
+        commands.remove_resource::<StreamReceiver>();
+    }
 }
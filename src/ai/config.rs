@@ -1,32 +1,199 @@
-use std::{fs, io::ErrorKind};
+use std::{fmt, fs, io, path::Path};
 
 use bevy::prelude::*;
-use ron::ser::PrettyConfig;
-use serde::{Deserialize, Serialize};
+use ron::value::RawValue;
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{MapAccess, Visitor},
+};
 
-#[derive(Default, Deserialize, Serialize, Resource)]
+use super::{
+    backend::Provider,
+    load::{LoadReport, read_ron},
+};
+
+pub(crate) const CONFIG_PATH: &str = "config.ron";
+/// The fields of [`Config`]. Other fields found in the file are kept when it
+/// is saved.
+const FIELDS: &[&str] = &[
+    "provider",
+    "api_key",
+    "base_url",
+    "model",
+    "temperature",
+    "top_p",
+    "max_tokens",
+];
+
+#[derive(Asset, TypePath, Clone, Default, Deserialize, Serialize, Resource)]
 pub(crate) struct Config {
+    #[serde(default)]
+    pub(crate) provider: Provider,
     pub(crate) api_key: String,
+    /// Overrides the provider's default API root when not empty.
+    #[serde(default)]
+    pub(crate) base_url: String,
+    /// Overrides the provider's default model when not empty.
+    #[serde(default)]
+    pub(crate) model: String,
+    /// Sampling temperature, left to the provider when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) temperature: Option<f32>,
+    /// Nucleus sampling probability, left to the provider when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) top_p: Option<f32>,
+    /// The longest answer in tokens, left to the provider when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) max_tokens: Option<u32>,
 }
 
-impl Config {
-    pub(crate) fn get_or_init() -> Config {
-        match fs::read("config.ron") {
-            Ok(file) => {
-                let config_str = String::from_utf8(file).unwrap();
-                let config: Config = ron::from_str(&config_str).unwrap();
-                config
+/// The fields of a RON struct with their values as written.
+struct RawFields(Vec<(String, Box<RawValue>)>);
+
+impl<'de> Deserialize<'de> for RawFields {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldsVisitor;
+
+        impl<'de> Visitor<'de> for FieldsVisitor {
+            type Value = RawFields;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a struct")
             }
-            Err(err) => match err.kind() {
-                ErrorKind::NotFound => {
-                    let config: Config = Config::default();
-                    let config_str =
-                        ron::ser::to_string_pretty(&config, PrettyConfig::default()).unwrap();
-                    fs::write("config.ron", config_str).unwrap();
-                    config
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<RawFields, A::Error> {
+                let mut fields = Vec::new();
+                while let Some(field) = map.next_entry()? {
+                    fields.push(field);
+                }
+                Ok(RawFields(fields))
+            }
+        }
+
+        deserializer.deserialize_struct("Config", &[], FieldsVisitor)
+    }
+}
+
+/// Writes the fields as a RON struct, one per line.
+impl fmt::Display for RawFields {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "(")?;
+        for (name, value) in &self.0 {
+            writeln!(f, "    {name}: {},", value.get_ron().trim())?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Config {
+    /// Loads [`CONFIG_PATH`], writing a default config if it is missing.
+    ///
+    /// A file that can't be loaded is backed up and replaced with the default
+    /// config, and noted in `report`.
+    pub(crate) fn get_or_init(report: &mut LoadReport) -> Config {
+        let path = Path::new(CONFIG_PATH);
+        match read_ron(path) {
+            Ok(config) => return config,
+            Err(err) if err.is_not_found() => report.first_run = true,
+            Err(err) => {
+                if !report.back_up(path, err) {
+                    return Config::default();
                 }
-                _ => panic!("{err}"),
-            },
+            }
         }
+
+        let config = Config::default();
+        if let Err(err) = config.save() {
+            log::error!("Failed to write {CONFIG_PATH}: {err}");
+        }
+        config
+    }
+
+    /// Writes the config to [`CONFIG_PATH`], keeping the fields of the old
+    /// file that aren't [`FIELDS`], e.g. ones written by a newer version.
+    pub(crate) fn save(&self) -> io::Result<()> {
+        self.save_to(Path::new(CONFIG_PATH))
+    }
+
+    /// Writes the config to `path` like [`Config::save`].
+    ///
+    /// The content is written to a temporary file first and then renamed over
+    /// the old one, so an interrupted write never leaves a truncated config.
+    fn save_to(&self, path: &Path) -> io::Result<()> {
+        let config_str = ron::to_string(self).map_err(io::Error::other)?;
+        let RawFields(mut fields) = ron::from_str(&config_str).map_err(io::Error::other)?;
+
+        let old_fields = fs::read_to_string(path)
+            .ok()
+            .and_then(|old| ron::from_str::<RawFields>(&old).ok())
+            .map(|RawFields(fields)| fields)
+            .unwrap_or_default();
+        fields.extend(
+            old_fields
+                .into_iter()
+                .filter(|(name, _)| !FIELDS.contains(&name.as_str())),
+        );
+
+        let temp_path = path.with_extension("ron.tmp");
+        fs::write(&temp_path, RawFields(fields).to_string())?;
+        fs::rename(temp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_unknown_fields_when_saving() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_PATH);
+        fs::write(
+            &path,
+            r#"(
+    provider: Anthropic,
+    api_key: "old",
+    theme: Dark,
+    shortcuts: {"send": "Enter"},
+    max_tokens: Some(100),
+)"#,
+        )
+        .unwrap();
+
+        let mut config: Config = read_ron(&path).unwrap();
+        config.api_key = "new".to_string();
+        config.temperature = Some(0.5);
+        config.max_tokens = None;
+        config.save_to(&path).unwrap();
+
+        let saved: Config = read_ron(&path).unwrap();
+        assert_eq!(saved.provider, Provider::Anthropic);
+        assert_eq!(saved.api_key, "new");
+        assert_eq!(saved.temperature, Some(0.5));
+        assert_eq!(saved.max_tokens, None);
+
+        let RawFields(fields) = read_ron(&path).unwrap();
+        let unknown: Vec<_> = fields
+            .iter()
+            .filter(|(name, _)| !FIELDS.contains(&name.as_str()))
+            .map(|(name, value)| (name.as_str(), value.get_ron().trim()))
+            .collect();
+        assert_eq!(
+            unknown,
+            [("theme", "Dark"), ("shortcuts", r#"{"send": "Enter"}"#)]
+        );
+        assert!(!path.with_extension("ron.tmp").exists());
+    }
+
+    #[test]
+    fn saves_without_an_old_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_PATH);
+
+        Config::default().save_to(&path).unwrap();
+
+        let saved: Config = read_ron(&path).unwrap();
+        assert_eq!(saved.provider, Provider::default());
+        assert!(saved.api_key.is_empty());
     }
 }
@@ -0,0 +1,244 @@
+use std::time::SystemTime;
+
+use bevy::prelude::*;
+
+use super::{
+    BranchSwitched, Dialog,
+    chat::{PendingMessages, StreamReceiver, cancel_chat, cancel_chat_if_left},
+};
+
+/// A stored conversation as listed in the sidebar.
+pub(crate) struct ConversationInfo {
+    pub(crate) id: String,
+    pub(crate) title: String,
+    pub(crate) modified: SystemTime,
+}
+
+/// All stored conversations, most recently modified first.
+#[derive(Resource, Deref)]
+pub(crate) struct Conversations(Vec<ConversationInfo>);
+
+impl Conversations {
+    pub(crate) fn load() -> Self {
+        Self(
+            Dialog::list()
+                .into_iter()
+                .filter_map(|(id, modified)| match Dialog::load(&id) {
+                    Ok(dialog) => Some(ConversationInfo {
+                        id,
+                        title: dialog.title(),
+                        modified,
+                    }),
+                    Err(err) => {
+                        log::warn!("Failed to load conversation {id}: {err}");
+                        None
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// Updates the entry of `dialog`, adding it if it is new, and moves it to
+    /// the top.
+    pub(super) fn touch(&mut self, dialog: &Dialog) {
+        self.0.retain(|info| info.id != dialog.id);
+        self.0.insert(
+            0,
+            ConversationInfo {
+                id: dialog.id.clone(),
+                title: dialog.title(),
+                modified: SystemTime::now(),
+            },
+        );
+    }
+}
+
+/// Starts a new conversation with the current system prompt.
+#[derive(Message, Clone)]
+pub(crate) struct NewConversation;
+
+/// Opens the conversation with this id.
+#[derive(Message, Clone)]
+pub(crate) struct SwitchConversation(pub(crate) String);
+
+#[derive(Message, Clone)]
+pub(crate) struct RenameConversation {
+    pub(crate) id: String,
+    pub(crate) name: String,
+}
+
+#[derive(Message, Clone)]
+pub(crate) struct DeleteConversation(pub(crate) String);
+
+/// Opens a conversation on the branch through one of its messages.
+#[derive(Message, Clone)]
+pub(crate) struct ShowMessage {
+    pub(crate) conversation: String,
+    pub(crate) index: usize,
+}
+
+/// Another conversation replaced the [`Dialog`] resource.
+#[derive(Message, Clone)]
+pub(crate) struct ConversationSwitched;
+
+/// Replaces the dialog, stopping the answer being streamed. Pending messages
+/// of the old one wait until it is opened again.
+fn switch_to(
+    new_dialog: Dialog,
+    dialog: &mut Dialog,
+    stream_receiver: &mut Option<ResMut<StreamReceiver>>,
+    conversation_switched: &mut MessageWriter<ConversationSwitched>,
+    commands: &mut Commands,
+) {
+    if cancel_chat(dialog, stream_receiver, commands)
+        && let Err(err) = dialog.save()
+    {
+        log::error!("Failed to save dialog: {err}");
+    }
+
+    *dialog = new_dialog;
+    conversation_switched.write(ConversationSwitched);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn on_conversation_action(
+    mut commands: Commands,
+    mut new_conversation: MessageReader<NewConversation>,
+    mut switch_conversation: MessageReader<SwitchConversation>,
+    mut rename_conversation: MessageReader<RenameConversation>,
+    mut delete_conversation: MessageReader<DeleteConversation>,
+    mut show_message: MessageReader<ShowMessage>,
+    mut conversation_switched: MessageWriter<ConversationSwitched>,
+    mut branch_switched: MessageWriter<BranchSwitched>,
+    mut conversations: ResMut<Conversations>,
+    mut dialog: ResMut<Dialog>,
+    mut pending_messages: ResMut<PendingMessages>,
+    mut stream_receiver: Option<ResMut<StreamReceiver>>,
+) {
+    for RenameConversation { id, name } in rename_conversation.read() {
+        let name = name.trim().to_string();
+        let renamed = if *id == dialog.id {
+            dialog.name = name;
+            dialog.save().map(|()| dialog.title())
+        } else {
+            Dialog::load(id).and_then(|mut other| {
+                other.name = name;
+                other.save().map(|()| other.title())
+            })
+        };
+        match renamed {
+            Ok(title) => {
+                if let Some(info) = conversations.0.iter_mut().find(|info| info.id == *id) {
+                    info.title = title;
+                }
+            }
+            Err(err) => log::error!("Failed to rename conversation {id}: {err}"),
+        }
+    }
+
+    let mut new_conversation_created = false;
+    for DeleteConversation(id) in delete_conversation.read() {
+        if let Err(err) = Dialog::delete(id) {
+            log::error!("Failed to delete conversation {id}: {err}");
+            continue;
+        }
+        conversations.0.retain(|info| info.id != *id);
+        pending_messages.remove(id);
+        if *id != dialog.id {
+            continue;
+        }
+
+        // The answer being streamed belongs to the deleted conversation.
+        if let Some(mut stream_receiver) = stream_receiver.take() {
+            stream_receiver.interrupt();
+            commands.remove_resource::<StreamReceiver>();
+        }
+        // Open the most recent remaining conversation, or start a new one.
+        match conversations
+            .iter()
+            .find_map(|info| Dialog::load(&info.id).ok())
+        {
+            Some(next) => switch_to(
+                next,
+                &mut dialog,
+                &mut stream_receiver,
+                &mut conversation_switched,
+                &mut commands,
+            ),
+            None => new_conversation_created = true,
+        }
+    }
+
+    if new_conversation.read().count() > 0 || new_conversation_created {
+        let mut new_dialog = dialog.new_like();
+        if let Err(err) = new_dialog.create() {
+            log::error!("Failed to save dialog: {err}");
+        }
+        switch_to(
+            new_dialog,
+            &mut dialog,
+            &mut stream_receiver,
+            &mut conversation_switched,
+            &mut commands,
+        );
+        conversations.touch(&dialog);
+    }
+
+    if let Some(SwitchConversation(id)) = switch_conversation.read().last()
+        && *id != dialog.id
+    {
+        match Dialog::load(id) {
+            Ok(new_dialog) => switch_to(
+                new_dialog,
+                &mut dialog,
+                &mut stream_receiver,
+                &mut conversation_switched,
+                &mut commands,
+            ),
+            Err(err) => log::error!("Failed to open conversation {id}: {err}"),
+        }
+    }
+
+    if let Some(ShowMessage {
+        conversation,
+        index,
+    }) = show_message.read().last()
+    {
+        if *conversation != dialog.id {
+            match Dialog::load(conversation) {
+                Ok(new_dialog) => switch_to(
+                    new_dialog,
+                    &mut dialog,
+                    &mut stream_receiver,
+                    &mut conversation_switched,
+                    &mut commands,
+                ),
+                Err(err) => log::error!("Failed to open conversation {conversation}: {err}"),
+            }
+        }
+        if *conversation == dialog.id
+            && dialog.get(*index).is_some()
+            && !dialog.branch().contains(index)
+        {
+            dialog.select(*index);
+            cancel_chat_if_left(&mut dialog, &mut stream_receiver, &mut commands);
+            if let Err(err) = dialog.save() {
+                log::error!("Failed to save dialog: {err}");
+            }
+            branch_switched.write(BranchSwitched);
+        }
+    }
+}
+
+/// Keeps the entry of the current conversation up to date as messages are
+/// added.
+pub(crate) fn update_conversations(
+    dialog: Res<Dialog>,
+    mut conversations: ResMut<Conversations>,
+    mut conversation_switched: MessageReader<ConversationSwitched>,
+) {
+    let is_switched = conversation_switched.read().count() > 0;
+    if dialog.is_changed() && !dialog.is_added() && !is_switched {
+        conversations.touch(&dialog);
+    }
+}
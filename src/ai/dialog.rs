@@ -1,40 +1,626 @@
-use std::{fs, io::ErrorKind};
+use std::{
+    cmp::Reverse,
+    fs::{self, OpenOptions},
+    io, iter,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use bevy::prelude::*;
 use ron::ser::PrettyConfig;
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Resource, Deref)]
-pub(crate) struct Dialog(pub(crate) Vec<deepseek_api::message::Message>);
+use super::{
+    Message, Role,
+    error::LoadError,
+    load::{LoadReport, read_ron},
+    message::LegacyMessage,
+};
+
+/// The directory holding one file per conversation.
+pub(crate) const CONVERSATIONS_DIR: &str = "conversations";
+/// Where the single dialog was kept before conversations were introduced.
+const LEGACY_DIALOG_PATH: &str = "dialog.ron";
+
+/// A message in the branch tree of a [`Dialog`].
+#[derive(Clone, Deserialize, Serialize)]
+struct DialogNode {
+    /// The index of the message this one follows, `None` for a first message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent: Option<usize>,
+    message: Message,
+}
+
+/// The current conversation.
+///
+/// Messages form a tree: editing a message adds the new version as a sibling
+/// of the old one, so every earlier version and its answers are kept. Only
+/// one branch of the tree is shown and sent to the model.
+#[derive(Asset, TypePath, Deserialize, Serialize, Resource)]
+#[serde(try_from = "StoredDialog")]
+pub(crate) struct Dialog {
+    /// The file stem of the conversation in [`CONVERSATIONS_DIR`].
+    #[serde(skip)]
+    pub(crate) id: String,
+    /// The name given by the user, empty if they haven't named it.
+    pub(crate) name: String,
+    /// Every message of every branch. A message always comes after the one
+    /// it follows.
+    nodes: Vec<DialogNode>,
+    /// The index of the last message of the shown branch.
+    current: Option<usize>,
+}
+
+/// The file format of a [`Dialog`], also accepting the flat message list
+/// written before branches were kept.
+#[derive(Deserialize)]
+struct StoredDialog {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    nodes: Vec<DialogNode>,
+    #[serde(default)]
+    current: Option<usize>,
+    #[serde(default)]
+    messages: Vec<Message>,
+}
+
+impl TryFrom<StoredDialog> for Dialog {
+    type Error = String;
+
+    fn try_from(stored: StoredDialog) -> Result<Self, Self::Error> {
+        let mut dialog = Dialog {
+            id: String::new(),
+            name: stored.name,
+            nodes: stored.nodes,
+            current: stored.current,
+        };
+        if dialog.nodes.is_empty() {
+            for message in stored.messages {
+                dialog.push(message);
+            }
+        }
+
+        for (index, node) in dialog.nodes.iter().enumerate() {
+            if node.parent.is_some_and(|parent| parent >= index) {
+                return Err(format!("message {index} follows a later message"));
+            }
+        }
+        if dialog
+            .current
+            .is_some_and(|current| current >= dialog.nodes.len())
+        {
+            return Err("the shown branch ends at a missing message".to_string());
+        }
+        Ok(dialog)
+    }
+}
 
 impl Default for Dialog {
     fn default() -> Self {
-        Self(vec![
-            deepseek_api::message::Message::system("你是一个智能助手。"),
-            deepseek_api::message::Message::user("你好！"),
+        Self::new(vec![
+            Message::system("你是一个智能助手。"),
+            Message::user("你好！"),
         ])
     }
 }
 
 impl Dialog {
-    pub(crate) fn get_or_init() -> Dialog {
-        match fs::read("dialog.ron") {
-            Ok(file) => {
-                let dialog_str = String::from_utf8(file).unwrap();
-                let dialog: Vec<deepseek_api::message::Message> =
-                    ron::from_str(&dialog_str).unwrap();
-                Dialog(dialog)
-            }
-            Err(err) => match err.kind() {
-                ErrorKind::NotFound => {
-                    let dialog: Dialog = Dialog::default();
-                    let dialog_str =
-                        ron::ser::to_string_pretty(&dialog.0, PrettyConfig::default()).unwrap();
-                    fs::write("dialog.ron", dialog_str).unwrap();
-                    dialog
+    /// Creates an unnamed conversation. Its id is only claimed when it is
+    /// [created](Self::create).
+    pub(crate) fn new(messages: Vec<Message>) -> Self {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let mut dialog = Self {
+            id: millis.to_string(),
+            name: String::new(),
+            nodes: Vec::new(),
+            current: None,
+        };
+        for message in messages {
+            dialog.push(message);
+        }
+        dialog
+    }
+
+    /// Creates an empty conversation with the same system prompt.
+    pub(crate) fn new_like(&self) -> Self {
+        Self::new(
+            self.messages()
+                .filter(|message| message.role == Role::System)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// The indices of the messages of the shown branch, first to last.
+    pub(crate) fn branch(&self) -> Vec<usize> {
+        let mut branch: Vec<_> =
+            iter::successors(self.current, |&index| self.nodes[index].parent).collect();
+        branch.reverse();
+        branch
+    }
+
+    /// The index of the last message of the shown branch.
+    pub(crate) fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// Whether the message at `index` is `ancestor` or follows it, directly or
+    /// through other messages.
+    pub(crate) fn follows(&self, index: Option<usize>, ancestor: usize) -> bool {
+        iter::successors(index, |&index| self.nodes[index].parent).any(|index| index == ancestor)
+    }
+
+    /// The messages of the shown branch, first to last.
+    pub(crate) fn messages(&self) -> impl Iterator<Item = &Message> {
+        self.branch()
+            .into_iter()
+            .map(|index| &self.nodes[index].message)
+    }
+
+    pub(crate) fn message(&self, index: usize) -> &Message {
+        &self.nodes[index].message
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<&Message> {
+        self.nodes.get(index).map(|node| &node.message)
+    }
+
+    /// The system message the conversation starts with.
+    pub(crate) fn system_prompt(&self) -> Option<&str> {
+        self.nodes
+            .iter()
+            .find(|node| node.parent.is_none() && node.message.role == Role::System)
+            .map(|node| node.message.content.as_str())
+    }
+
+    /// Replaces the system message at the start of every branch, or adds one
+    /// before the first messages if there is none.
+    pub(crate) fn set_system_prompt(&mut self, prompt: &str) {
+        if self.system_prompt().is_none() {
+            for node in &mut self.nodes {
+                node.parent = Some(node.parent.map_or(0, |parent| parent + 1));
+            }
+            self.nodes.insert(
+                0,
+                DialogNode {
+                    parent: None,
+                    message: Message::system(prompt),
+                },
+            );
+            self.current = Some(self.current.map_or(0, |current| current + 1));
+            return;
+        }
+        for node in &mut self.nodes {
+            if node.parent.is_none() && node.message.role == Role::System {
+                node.message.content = prompt.to_string();
+            }
+        }
+    }
+
+    /// The messages of every branch with their indices.
+    pub(crate) fn all_messages(&self) -> impl Iterator<Item = (usize, &Message)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (index, &node.message))
+    }
+
+    /// Adds `message` to the end of the shown branch and returns its index.
+    pub(crate) fn push(&mut self, message: Message) -> usize {
+        let index = self.add_reply(self.current, message);
+        self.current = Some(index);
+        index
+    }
+
+    /// Adds `message` after the message at `parent`, or as a first message,
+    /// without changing the shown branch, and returns its index.
+    pub(crate) fn add_reply(&mut self, parent: Option<usize>, message: Message) -> usize {
+        self.nodes.push(DialogNode { parent, message });
+        self.nodes.len() - 1
+    }
+
+    /// Adds `content` as a new version of the message at `index` and shows
+    /// it, leaving the old version and its answers in their own branch.
+    pub(crate) fn edit(&mut self, index: usize, content: &str) -> usize {
+        let node = &self.nodes[index];
+        let message = Message::new(node.message.role, content);
+        self.nodes.push(DialogNode {
+            parent: node.parent,
+            message,
+        });
+        let index = self.nodes.len() - 1;
+        self.current = Some(index);
+        index
+    }
+
+    /// Shows the branch leading to the message at `index`, ending right before
+    /// it, so the next message pushed becomes another version of it.
+    pub(crate) fn rewind(&mut self, index: usize) {
+        self.current = self.nodes[index].parent;
+    }
+
+    /// Shows the branch ending with the message at `index`, so the next
+    /// message pushed becomes another reply to it.
+    pub(crate) fn end_at(&mut self, index: usize) {
+        self.current = Some(index);
+    }
+
+    /// Removes the message at `index`. The messages that followed it follow
+    /// the message before it instead.
+    pub(crate) fn remove(&mut self, index: usize) {
+        let removed = self.nodes.remove(index);
+        let shift = |other: usize| if other > index { other - 1 } else { other };
+        for node in &mut self.nodes {
+            node.parent = match node.parent {
+                Some(parent) if parent == index => removed.parent,
+                parent => parent.map(shift),
+            };
+        }
+        self.current = match self.current {
+            Some(current) if current == index => removed.parent,
+            current => current.map(shift),
+        };
+    }
+
+    /// The versions of the message at `index`, oldest first.
+    pub(crate) fn siblings(&self, index: usize) -> Vec<usize> {
+        let parent = self.nodes[index].parent;
+        (0..self.nodes.len())
+            .filter(|&sibling| self.nodes[sibling].parent == parent)
+            .collect()
+    }
+
+    /// Shows the branch through the message at `index`, continuing with the
+    /// latest answer after each message.
+    pub(crate) fn select(&mut self, index: usize) {
+        let mut current = index;
+        while let Some(reply) = (current + 1..self.nodes.len())
+            .rfind(|&reply| self.nodes[reply].parent == Some(current))
+        {
+            current = reply;
+        }
+        self.current = Some(current);
+    }
+
+    pub(super) fn path(id: &str) -> PathBuf {
+        PathBuf::from(CONVERSATIONS_DIR).join(format!("{id}.ron"))
+    }
+
+    pub(crate) fn load(id: &str) -> io::Result<Dialog> {
+        Ok(Self::read(id)?)
+    }
+
+    fn read(id: &str) -> Result<Dialog, LoadError> {
+        let mut dialog: Dialog = read_ron(&Self::path(id))?;
+        dialog.id = id.to_string();
+        Ok(dialog)
+    }
+
+    /// Loads the most recently modified conversation, creating the directory
+    /// on first run and moving the old `dialog.ron` into it.
+    ///
+    /// Files that can't be loaded are backed up, so they are no longer listed,
+    /// and noted in `report`. A new conversation is started if none is left.
+    pub(crate) fn get_or_init(report: &mut LoadReport) -> Dialog {
+        if let Err(err) = fs::create_dir_all(CONVERSATIONS_DIR) {
+            log::error!("Failed to create {CONVERSATIONS_DIR}: {err}");
+        }
+
+        let legacy_path = Path::new(LEGACY_DIALOG_PATH);
+        match read_legacy_dialog(legacy_path) {
+            Ok(messages) => {
+                let mut dialog = Dialog::new(messages);
+                if let Err(err) = dialog.create().and_then(|()| fs::remove_file(legacy_path)) {
+                    log::error!(
+                        "Failed to move {LEGACY_DIALOG_PATH} to {CONVERSATIONS_DIR}: {err}"
+                    );
+                }
+                return dialog;
+            }
+            Err(err) if err.is_not_found() => {}
+            Err(err) => {
+                report.back_up(legacy_path, err);
+            }
+        }
+
+        for (id, _) in Self::list() {
+            match Self::read(&id) {
+                Ok(dialog) => return dialog,
+                Err(err) => {
+                    report.back_up(&Self::path(&id), err);
+                }
+            }
+        }
+
+        let mut dialog = Dialog::default();
+        if let Err(err) = dialog.create() {
+            log::error!("Failed to save dialog: {err}");
+        }
+        dialog
+    }
+
+    /// The ids and modification times of all stored conversations, most
+    /// recently modified first.
+    pub(crate) fn list() -> Vec<(String, SystemTime)> {
+        let entries = match fs::read_dir(CONVERSATIONS_DIR) {
+            Ok(entries) => entries,
+            Err(err) => {
+                log::error!("Failed to list conversations: {err}");
+                return Vec::new();
+            }
+        };
+        let mut conversations: Vec<_> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "ron" {
+                    return None;
                 }
-                _ => panic!("{err}"),
-            },
+                let id = path.file_stem()?.to_str()?.to_string();
+                let modified = path.metadata().and_then(|metadata| metadata.modified());
+                Some((id, modified.unwrap_or(UNIX_EPOCH)))
+            })
+            .collect();
+        conversations.sort_by_key(|(_, modified)| Reverse(*modified));
+        conversations
+    }
+
+    /// The name shown for the conversation: its given name, or else the start
+    /// of the first question.
+    pub(crate) fn title(&self) -> String {
+        if !self.name.is_empty() {
+            return self.name.clone();
         }
+        self.messages()
+            .find(|message| message.role == Role::User)
+            .map(|message| {
+                let line = message.content.lines().next().unwrap_or_default();
+                let mut title: String = line.chars().take(24).collect();
+                if title.len() < line.len() {
+                    title.push('…');
+                }
+                title
+            })
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| "New conversation".to_string())
+    }
+
+    /// Writes the dialog to its file in [`CONVERSATIONS_DIR`].
+    ///
+    /// The content is written to a temporary file first and then renamed over
+    /// the old one, so an interrupted write never leaves a truncated dialog.
+    pub(crate) fn save(&self) -> io::Result<()> {
+        self.save_to(&Self::path(&self.id))
+    }
+
+    fn save_to(&self, path: &Path) -> io::Result<()> {
+        let dialog_str =
+            ron::ser::to_string_pretty(self, PrettyConfig::default()).map_err(io::Error::other)?;
+        let temp_path = path.with_extension("ron.tmp");
+        fs::write(&temp_path, dialog_str)?;
+        fs::rename(temp_path, path)
+    }
+
+    /// Saves a new conversation to [`CONVERSATIONS_DIR`] under its id, or the
+    /// id with the first free `-1`, `-2`, ... suffix.
+    pub(crate) fn create(&mut self) -> io::Result<()> {
+        self.create_in(Path::new(CONVERSATIONS_DIR))
+    }
+
+    /// Saves a new conversation to `dir`. Its file is claimed before it is
+    /// written, so conversations created at the same time, e.g. by imports
+    /// running in the background, never get the same id.
+    pub(super) fn create_in(&mut self, dir: &Path) -> io::Result<()> {
+        let base = self.id.clone();
+        let mut suffix = 0;
+        loop {
+            let id = if suffix == 0 {
+                base.clone()
+            } else {
+                format!("{base}-{suffix}")
+            };
+            let path = dir.join(format!("{id}.ron"));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => {
+                    self.id = id;
+                    return self.save_to(&path);
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => suffix += 1,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    pub(crate) fn delete(id: &str) -> io::Result<()> {
+        fs::remove_file(Self::path(id))
+    }
+}
+
+/// Reads the messages of the old `dialog.ron`, in the current format or in
+/// the one of the `deepseek-api` crate before it.
+fn read_legacy_dialog(path: &Path) -> Result<Vec<Message>, LoadError> {
+    match read_ron::<Vec<Message>>(path) {
+        Err(err @ LoadError::Parse { .. }) => read_ron::<Vec<LegacyMessage>>(path)
+            .map(|messages| {
+                messages
+                    .into_iter()
+                    .filter_map(LegacyMessage::into_message)
+                    .collect()
+            })
+            // The error in the current format is the one worth reporting.
+            .map_err(|_| err),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(dialog: &Dialog) -> Vec<&str> {
+        dialog
+            .messages()
+            .map(|message| message.content.as_str())
+            .collect()
+    }
+
+    /// System prompt, question (1) and answer (2).
+    fn dialog() -> Dialog {
+        Dialog::new(vec![
+            Message::system("prompt"),
+            Message::user("question"),
+            Message::assistant("answer"),
+        ])
+    }
+
+    #[test]
+    fn keeps_the_old_version_of_an_edited_message() {
+        let mut dialog = dialog();
+
+        let edited = dialog.edit(1, "better question");
+        assert_eq!(contents(&dialog), ["prompt", "better question"]);
+        assert_eq!(dialog.siblings(edited), [1, edited]);
+
+        dialog.push(Message::assistant("better answer"));
+        dialog.select(1);
+        assert_eq!(contents(&dialog), ["prompt", "question", "answer"]);
+        dialog.select(edited);
+        assert_eq!(
+            contents(&dialog),
+            ["prompt", "better question", "better answer"]
+        );
+    }
+
+    #[test]
+    fn regenerates_next_to_an_answer_or_after_a_question() {
+        let mut dialog = dialog();
+
+        dialog.rewind(2);
+        assert_eq!(contents(&dialog), ["prompt", "question"]);
+        let regenerated = dialog.push(Message::assistant("another answer"));
+        assert_eq!(dialog.siblings(regenerated), [2, regenerated]);
+
+        dialog.end_at(1);
+        let reply = dialog.push(Message::assistant("third answer"));
+        assert_eq!(dialog.siblings(reply), [2, regenerated, reply]);
+        assert_eq!(contents(&dialog), ["prompt", "question", "third answer"]);
+    }
+
+    #[test]
+    fn selects_the_latest_answer_of_a_version() {
+        let mut dialog = dialog();
+        let answer = dialog.push(Message::assistant("later answer"));
+        dialog.rewind(2);
+        let other = dialog.push(Message::assistant("other answer"));
+
+        dialog.select(2);
+        assert_eq!(dialog.current(), Some(answer));
+        dialog.select(0);
+        assert_eq!(dialog.current(), Some(other));
+        assert!(dialog.follows(Some(answer), 1));
+        assert!(!dialog.follows(Some(other), 2));
+    }
+
+    #[test]
+    fn reattaches_the_answers_of_a_deleted_message() {
+        let mut dialog = dialog();
+        let follow_up = dialog.push(Message::user("follow-up"));
+
+        dialog.remove(2);
+        assert_eq!(contents(&dialog), ["prompt", "question", "follow-up"]);
+        assert_eq!(dialog.current(), Some(follow_up - 1));
+        assert_eq!(dialog.siblings(follow_up - 1), [follow_up - 1]);
+
+        dialog.remove(follow_up - 1);
+        assert_eq!(contents(&dialog), ["prompt", "question"]);
+        assert_eq!(dialog.current(), Some(1));
+    }
+
+    #[test]
+    fn inserts_a_missing_system_prompt() {
+        let mut dialog = Dialog::new(vec![Message::user("question")]);
+        let edited = dialog.edit(0, "better question");
+
+        dialog.set_system_prompt("prompt");
+        assert_eq!(contents(&dialog), ["prompt", "better question"]);
+        assert_eq!(dialog.siblings(edited + 1), [1, edited + 1]);
+
+        dialog.set_system_prompt("new prompt");
+        assert_eq!(dialog.system_prompt(), Some("new prompt"));
+    }
+
+    #[test]
+    fn migrates_the_flat_message_list() {
+        let dialog: Dialog = ron::from_str(
+            r#"(
+                name: "Old",
+                messages: [
+                    (role: System, content: "prompt"),
+                    (role: User, content: "question"),
+                ],
+            )"#,
+        )
+        .unwrap();
+
+        assert_eq!(dialog.name, "Old");
+        assert_eq!(contents(&dialog), ["prompt", "question"]);
+        assert_eq!(dialog.current(), Some(1));
+    }
+
+    #[test]
+    fn reads_the_dialog_of_the_first_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dialog.ron");
+        // As written by `deepseek-api` messages.
+        fs::write(
+            &path,
+            r#"[
+    System((
+        content: "你是一个智能助手。",
+        name: None,
+    )),
+    User((
+        content: "你好！",
+        name: None,
+    )),
+    Assistant((
+        content: "你好！有什么可以帮你的吗？",
+        name: None,
+        prefix: None,
+        reasoning_content: None,
+    )),
+]"#,
+        )
+        .unwrap();
+
+        let messages = read_legacy_dialog(&path).unwrap();
+        let roles: Vec<_> = messages.iter().map(|message| message.role).collect();
+        assert_eq!(roles, [Role::System, Role::User, Role::Assistant]);
+        assert_eq!(messages[2].content, "你好！有什么可以帮你的吗？");
+        assert_eq!(messages[2].created, None);
+
+        fs::write(&path, r#"[(role: User, content: "question")]"#).unwrap();
+        assert_eq!(read_legacy_dialog(&path).unwrap()[0].content, "question");
+
+        fs::write(&path, "[Unknown(())]").unwrap();
+        assert!(matches!(
+            read_legacy_dialog(&path),
+            Err(LoadError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_broken_trees() {
+        let result = ron::from_str::<Dialog>(
+            r#"(nodes: [(parent: Some(1), message: (role: User, content: "a"))])"#,
+        );
+        assert!(result.is_err());
+        let result = ron::from_str::<Dialog>(
+            r#"(nodes: [(message: (role: User, content: "a"))], current: Some(1))"#,
+        );
+        assert!(result.is_err());
     }
 }
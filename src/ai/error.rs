@@ -0,0 +1,149 @@
+use std::{fmt, io};
+
+use reqwest::StatusCode;
+
+/// Why a chat request failed.
+#[derive(Clone, Debug)]
+pub(crate) enum ChatError {
+    /// The API key is missing, invalid or lacks permission.
+    Auth(String),
+    /// The provider is throttling requests.
+    RateLimit(String),
+    /// The server could not be reached or the connection dropped.
+    Network(String),
+    /// The server failed to handle a valid request.
+    Server(String),
+    /// The server rejected the request, e.g. because the model doesn't exist.
+    Request(String),
+    /// The response could not be understood.
+    MalformedStream(String),
+    /// The config lacks something the request needs, e.g. a model name.
+    Config(String),
+}
+
+impl ChatError {
+    /// Classifies an unsuccessful HTTP response.
+    pub(crate) fn from_status(status: StatusCode, detail: String) -> Self {
+        let detail = if detail.is_empty() {
+            status.to_string()
+        } else {
+            format!("{status}: {detail}")
+        };
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ChatError::Auth(detail),
+            StatusCode::TOO_MANY_REQUESTS => ChatError::RateLimit(detail),
+            status if status.is_server_error() => ChatError::Server(detail),
+            _ => ChatError::Request(detail),
+        }
+    }
+
+    pub(crate) fn title(&self) -> &'static str {
+        match self {
+            ChatError::Auth(_) => "Authentication failed",
+            ChatError::RateLimit(_) => "Rate limited",
+            ChatError::Network(_) => "Network error",
+            ChatError::Server(_) => "Server error",
+            ChatError::Request(_) => "Request rejected",
+            ChatError::MalformedStream(_) => "Malformed response",
+            ChatError::Config(_) => "Incomplete settings",
+        }
+    }
+
+    pub(crate) fn detail(&self) -> &str {
+        match self {
+            ChatError::Auth(detail)
+            | ChatError::RateLimit(detail)
+            | ChatError::Network(detail)
+            | ChatError::Server(detail)
+            | ChatError::Request(detail)
+            | ChatError::MalformedStream(detail)
+            | ChatError::Config(detail) => detail,
+        }
+    }
+}
+
+impl fmt::Display for ChatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.title(), self.detail())
+    }
+}
+
+impl std::error::Error for ChatError {}
+
+impl From<reqwest::Error> for ChatError {
+    fn from(err: reqwest::Error) -> Self {
+        match err.status() {
+            Some(status) => ChatError::from_status(status, String::new()),
+            None if err.is_decode() => ChatError::MalformedStream(err.to_string()),
+            None => ChatError::Network(err.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ChatError {
+    fn from(err: serde_json::Error) -> Self {
+        ChatError::MalformedStream(err.to_string())
+    }
+}
+
+/// Why a file of the app couldn't be loaded.
+#[derive(Debug)]
+pub(crate) enum LoadError {
+    /// The file couldn't be read.
+    Io(io::Error),
+    /// The file isn't UTF-8 text.
+    Encoding,
+    /// The file isn't valid RON, or doesn't hold what it should.
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl LoadError {
+    pub(crate) fn is_not_found(&self) -> bool {
+        matches!(self, LoadError::Io(err) if err.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "{err}"),
+            LoadError::Encoding => write!(f, "The file isn't UTF-8 text"),
+            LoadError::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "Line {line}, column {column}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for LoadError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        LoadError::Parse {
+            line: err.span.start.line,
+            column: err.span.start.col,
+            message: err.code.to_string(),
+        }
+    }
+}
+
+impl From<LoadError> for io::Error {
+    fn from(err: LoadError) -> Self {
+        match err {
+            LoadError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
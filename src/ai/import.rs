@@ -0,0 +1,518 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task, futures::check_ready},
+};
+use serde_json::Value;
+
+use super::{Conversations, Dialog, Message, Role, dialog::CONVERSATIONS_DIR};
+
+/// Adds the conversations of a ChatGPT `conversations.json` export, or of
+/// OpenAI-style `messages` JSON, to the stored ones.
+#[derive(Message, Clone)]
+pub(crate) struct ImportConversations(pub(crate) PathBuf);
+
+/// An [`ImportConversations`] is done.
+#[derive(Message, Clone)]
+pub(crate) struct ConversationsImported {
+    /// How many conversations were added.
+    pub(crate) count: usize,
+    /// Why conversations or messages were left out, one entry each.
+    pub(crate) errors: Vec<String>,
+}
+
+/// The files being imported in the background.
+#[derive(Resource, Default)]
+pub(crate) struct ImportTasks(Vec<Task<Imported>>);
+
+/// The outcome of importing one file.
+struct Imported {
+    path: PathBuf,
+    /// The conversations that were added, already saved.
+    dialogs: Vec<Dialog>,
+    errors: Vec<String>,
+}
+
+/// Starts reading the files to import in the background.
+pub(crate) fn on_import(
+    mut import_conversations: MessageReader<ImportConversations>,
+    mut import_tasks: ResMut<ImportTasks>,
+) {
+    for ImportConversations(path) in import_conversations.read() {
+        let path = path.clone();
+        import_tasks.0.push(
+            AsyncComputeTaskPool::get()
+                .spawn(async move { import(path, Path::new(CONVERSATIONS_DIR)) }),
+        );
+    }
+}
+
+/// Lists the conversations of the finished imports.
+pub(crate) fn finish_imports(
+    mut import_tasks: ResMut<ImportTasks>,
+    mut conversations_imported: MessageWriter<ConversationsImported>,
+    mut conversations: ResMut<Conversations>,
+) {
+    import_tasks.0.retain_mut(|task| {
+        let Some(Imported {
+            path,
+            dialogs,
+            errors,
+        }) = check_ready(task)
+        else {
+            return true;
+        };
+        for dialog in &dialogs {
+            conversations.touch(dialog);
+        }
+
+        log::info!(
+            "Imported {} conversations from {}",
+            dialogs.len(),
+            path.display()
+        );
+        for error in &errors {
+            log::warn!("Skipped while importing {}: {error}", path.display());
+        }
+        conversations_imported.write(ConversationsImported {
+            count: dialogs.len(),
+            errors,
+        });
+        false
+    });
+}
+
+/// Reads the conversations of the file at `path` and saves them to `dir`.
+fn import(path: PathBuf, dir: &Path) -> Imported {
+    let mut dialogs = Vec::new();
+    let mut errors = Vec::new();
+    let items = fs::read_to_string(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|json| parse_export(&json));
+    match items {
+        Ok(items) => {
+            for (position, item) in items.iter().enumerate() {
+                let Some(mut dialog) = to_dialog(position, item, &mut errors) else {
+                    continue;
+                };
+                match dialog.create_in(dir) {
+                    Ok(()) => dialogs.push(dialog),
+                    Err(err) => errors.push(format!("{}: {err}", dialog.title())),
+                }
+            }
+        }
+        Err(err) => errors.push(err),
+    }
+    Imported {
+        path,
+        dialogs,
+        errors,
+    }
+}
+
+/// Splits the content of an export into its conversations.
+fn parse_export(json: &str) -> Result<Vec<Value>, String> {
+    let value: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    match value {
+        // A bare `messages` array is a single conversation.
+        Value::Array(items) if items.iter().any(|item| item.get("role").is_some()) => {
+            Ok(vec![Value::Object(
+                [("messages".to_string(), Value::Array(items))]
+                    .into_iter()
+                    .collect(),
+            )])
+        }
+        Value::Array(items) => Ok(items),
+        Value::Object(ref object)
+            if object.contains_key("mapping") || object.contains_key("messages") =>
+        {
+            Ok(vec![value])
+        }
+        _ => Err("neither a ChatGPT export nor OpenAI messages".to_string()),
+    }
+}
+
+/// Creates the dialog of one conversation of an export, adding why parts of
+/// it were left out to `errors`.
+fn to_dialog(position: usize, item: &Value, errors: &mut Vec<String>) -> Option<Dialog> {
+    let title = item
+        .get("title")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .trim();
+    let label = if title.is_empty() {
+        format!("conversation {}", position + 1)
+    } else {
+        format!("\"{title}\"")
+    };
+
+    let dialog = if let Some(mapping) = item.get("mapping") {
+        from_chatgpt(mapping, item.get("current_node"), &label, errors)
+    } else if let Some(messages) = item.get("messages").and_then(Value::as_array) {
+        Some(from_messages(messages, &label, errors))
+    } else {
+        errors.push(format!("{label}: no messages"));
+        return None;
+    };
+
+    let mut dialog = dialog?;
+    if dialog.get(0).is_none() {
+        errors.push(format!("{label}: no messages"));
+        return None;
+    }
+    dialog.name = title.to_string();
+    Some(dialog)
+}
+
+/// Creates a dialog from a ChatGPT message tree, keeping every branch and
+/// showing the branch ending at `current_node`.
+fn from_chatgpt(
+    mapping: &Value,
+    current_node: Option<&Value>,
+    label: &str,
+    errors: &mut Vec<String>,
+) -> Option<Dialog> {
+    let Some(mapping) = mapping.as_object() else {
+        errors.push(format!("{label}: the message tree is not an object"));
+        return None;
+    };
+
+    let mut dialog = Dialog::new(Vec::new());
+    // The index in the dialog of each node, or of the nearest message before
+    // it for nodes that are left out.
+    let mut indices: HashMap<&str, Option<usize>> = HashMap::new();
+    let mut stack: Vec<(&str, Option<usize>)> = mapping
+        .iter()
+        .filter(|(_, node)| {
+            node.get("parent")
+                .and_then(Value::as_str)
+                .is_none_or(|parent| !mapping.contains_key(parent))
+        })
+        .map(|(id, _)| (id.as_str(), None))
+        .collect();
+    stack.reverse();
+    // Parents are visited before their children, so a message always comes
+    // after the one it follows.
+    while let Some((id, parent)) = stack.pop() {
+        if indices.contains_key(id) {
+            continue;
+        }
+        let node = &mapping[id];
+        let message = match node.get("message") {
+            None | Some(Value::Null) => None,
+            Some(message) => chatgpt_message(message).unwrap_or_else(|err| {
+                errors.push(format!("{label}, message {id}: {err}"));
+                None
+            }),
+        };
+        let index = match message {
+            Some(message) => Some(dialog.add_reply(parent, message)),
+            None => parent,
+        };
+        indices.insert(id, index);
+
+        let children = node.get("children").and_then(Value::as_array);
+        for child in children.into_iter().flatten().rev() {
+            if let Some(child) = child.as_str()
+                && mapping.contains_key(child)
+            {
+                stack.push((child, index));
+            }
+        }
+    }
+
+    let current = current_node
+        .and_then(Value::as_str)
+        .and_then(|id| indices.get(id).copied().flatten());
+    match current {
+        Some(current) => dialog.end_at(current),
+        None if dialog.get(0).is_some() => dialog.select(0),
+        None => {}
+    }
+    Some(dialog)
+}
+
+/// Converts a message of a ChatGPT export. Tool calls, hidden messages and
+/// content other than text are left out as `None`.
+fn chatgpt_message(value: &Value) -> Result<Option<Message>, String> {
+    let role = match value.pointer("/author/role").and_then(Value::as_str) {
+        Some("system") => Role::System,
+        Some("user") => Role::User,
+        Some("assistant") => Role::Assistant,
+        Some(_) => return Ok(None),
+        None => return Err("no author role".to_string()),
+    };
+    let is_hidden = value
+        .pointer("/metadata/is_visually_hidden_from_conversation")
+        .and_then(Value::as_bool)
+        .unwrap_or_default();
+    if is_hidden {
+        return Ok(None);
+    }
+
+    let Some(content) = value.get("content") else {
+        return Err("no content".to_string());
+    };
+    match content.get("content_type").and_then(Value::as_str) {
+        Some("text" | "multimodal_text") => {}
+        Some(_) => return Ok(None),
+        None => return Err("no content type".to_string()),
+    }
+    let Some(parts) = content.get("parts").and_then(Value::as_array) else {
+        return Err("no content parts".to_string());
+    };
+    // Other parts are images and files.
+    let text = parts
+        .iter()
+        .filter_map(Value::as_str)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let mut message = Message::new(role, &text);
+    message.created = value
+        .get("create_time")
+        .and_then(Value::as_f64)
+        .map(|seconds| seconds as u64);
+    message.model = value
+        .pointer("/metadata/model_slug")
+        .and_then(Value::as_str)
+        .filter(|_| role == Role::Assistant)
+        .map(str::to_string);
+    Ok(Some(message))
+}
+
+/// Creates a dialog from OpenAI-style messages, leaving out the ones that
+/// can't be read.
+fn from_messages(messages: &[Value], label: &str, errors: &mut Vec<String>) -> Dialog {
+    let mut dialog = Dialog::new(Vec::new());
+    for (position, value) in messages.iter().enumerate() {
+        match openai_message(value) {
+            Ok(message) => {
+                dialog.push(message);
+            }
+            Err(err) => errors.push(format!("{label}, message {}: {err}", position + 1)),
+        }
+    }
+    dialog
+}
+
+fn openai_message(value: &Value) -> Result<Message, String> {
+    let role = match value.get("role").and_then(Value::as_str) {
+        Some("system" | "developer") => Role::System,
+        Some("user") => Role::User,
+        Some("assistant") => Role::Assistant,
+        Some(role) => return Err(format!("unsupported role \"{role}\"")),
+        None => return Err("no role".to_string()),
+    };
+    let content = match value.get("content") {
+        Some(Value::String(content)) => content.clone(),
+        // Content parts, of which only the text is kept.
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| part.get("text").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    };
+    if content.trim().is_empty() {
+        return Err("no text content".to_string());
+    }
+
+    let mut message = Message::new(role, &content);
+    // Some exports carry these.
+    message.created = value.get("created").and_then(Value::as_u64);
+    message.model = value
+        .get("model")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn contents(dialog: &Dialog) -> Vec<&str> {
+        dialog
+            .messages()
+            .map(|message| message.content.as_str())
+            .collect()
+    }
+
+    fn chatgpt_node(role: &str, text: &str, parent: &str, children: &[&str]) -> Value {
+        json!({
+            "message": {
+                "author": { "role": role },
+                "content": { "content_type": "text", "parts": [text] },
+                "create_time": 1700000000.5,
+                "metadata": { "model_slug": "gpt-4o" },
+            },
+            "parent": parent,
+            "children": children,
+        })
+    }
+
+    #[test]
+    fn imports_every_branch_of_a_chatgpt_conversation() {
+        let item = json!({
+            "title": "Branches",
+            "current_node": "answer-2",
+            "mapping": {
+                "root": { "message": null, "parent": null, "children": ["system"] },
+                "system": chatgpt_node("system", "", "root", &["question-1", "question-2"]),
+                "question-1": chatgpt_node("user", "Hi", "system", &["answer-1"]),
+                "answer-1": chatgpt_node("assistant", "Hello", "question-1", &[]),
+                "question-2": chatgpt_node("user", "Hi again", "system", &["answer-2", "broken"]),
+                "answer-2": chatgpt_node("assistant", "Hello again", "question-2", &["tool"]),
+                "tool": chatgpt_node("tool", "result", "answer-2", &[]),
+                "broken": { "message": { "content": {} }, "parent": "question-2", "children": [] },
+            },
+        });
+
+        let mut errors = Vec::new();
+        let dialog = to_dialog(0, &item, &mut errors).unwrap();
+
+        assert_eq!(dialog.name, "Branches");
+        assert_eq!(contents(&dialog), ["Hi again", "Hello again"]);
+        let branch = dialog.branch();
+        assert_eq!(dialog.siblings(branch[0]).len(), 2);
+        let answer = dialog.message(branch[1]);
+        assert_eq!(answer.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(answer.created, Some(1700000000));
+        assert_eq!(errors, ["\"Branches\", message broken: no author role"]);
+    }
+
+    #[test]
+    fn imports_openai_messages() {
+        let item = json!({
+            "messages": [
+                { "role": "developer", "content": "prompt" },
+                { "role": "tool", "content": "result" },
+                { "role": "user", "content": [{ "type": "text", "text": "Hi" }] },
+                { "role": "assistant", "content": "" },
+                { "role": "assistant", "content": "Hello", "model": "gpt-4o", "created": 5 },
+            ],
+        });
+
+        let mut errors = Vec::new();
+        let dialog = to_dialog(1, &item, &mut errors).unwrap();
+
+        assert_eq!(dialog.name, "");
+        assert_eq!(contents(&dialog), ["prompt", "Hi", "Hello"]);
+        assert_eq!(dialog.message(2).model.as_deref(), Some("gpt-4o"));
+        assert_eq!(dialog.message(2).created, Some(5));
+        assert_eq!(
+            errors,
+            [
+                "conversation 2, message 2: unsupported role \"tool\"",
+                "conversation 2, message 4: no text content",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_each_bad_conversation() {
+        let items = parse_export(
+            r#"[
+                { "title": "Empty", "mapping": {} },
+                { "title": "Broken", "mapping": [] },
+                { "title": "Nothing" },
+                { "messages": [{ "role": "user", "content": "Hi" }] }
+            ]"#,
+        )
+        .unwrap();
+
+        let mut errors = Vec::new();
+        let dialogs: Vec<_> = items
+            .iter()
+            .enumerate()
+            .filter_map(|(position, item)| to_dialog(position, item, &mut errors))
+            .collect();
+
+        assert_eq!(dialogs.len(), 1);
+        assert_eq!(contents(&dialogs[0]), ["Hi"]);
+        assert_eq!(
+            errors,
+            [
+                "\"Empty\": no messages",
+                "\"Broken\": the message tree is not an object",
+                "\"Nothing\": no messages",
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_exports_into_conversations() {
+        let messages = parse_export(r#"[{ "role": "user", "content": "Hi" }]"#).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].get("messages").is_some());
+
+        let conversation = parse_export(r#"{ "mapping": {} }"#).unwrap();
+        assert_eq!(conversation.len(), 1);
+
+        assert!(parse_export(r#"{ "name": "other" }"#).is_err());
+        assert!(parse_export("not json").is_err());
+    }
+
+    #[test]
+    fn keeps_every_conversation_of_concurrent_imports() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|name| {
+                let conversations: Vec<_> = (0..10)
+                    .map(|n| {
+                        json!({
+                            "title": format!("{name}{n}"),
+                            "messages": [{ "role": "user", "content": "Hi" }],
+                        })
+                    })
+                    .collect();
+                let path = dir.path().join(format!("{name}.json"));
+                fs::write(&path, Value::Array(conversations).to_string()).unwrap();
+                path
+            })
+            .collect();
+        let conversations_dir = dir.path().join("conversations");
+        fs::create_dir(&conversations_dir).unwrap();
+
+        let imported: Vec<_> = std::thread::scope(|scope| {
+            let threads: Vec<_> = paths
+                .into_iter()
+                .map(|path| scope.spawn(|| import(path, &conversations_dir)))
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect()
+        });
+
+        let mut names = Vec::new();
+        for Imported {
+            dialogs, errors, ..
+        } in imported
+        {
+            assert_eq!(errors, Vec::<String>::new());
+            for dialog in dialogs {
+                let path = conversations_dir.join(format!("{}.ron", dialog.id));
+                let saved: Dialog = ron::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+                assert_eq!(saved.name, dialog.name);
+                names.push(saved.name);
+            }
+        }
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), 20);
+        assert_eq!(fs::read_dir(&conversations_dir).unwrap().count(), 20);
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+
+use super::error::LoadError;
+
+/// A file that couldn't be loaded at startup.
+pub(crate) struct LoadFailure {
+    pub(crate) path: PathBuf,
+    pub(crate) error: LoadError,
+    /// Where the file was moved before a fresh one replaced it, `None` if it
+    /// couldn't be moved and was left as it is.
+    pub(crate) backup: Option<PathBuf>,
+}
+
+/// What went wrong while loading the config and the conversation at startup.
+#[derive(Resource, Default)]
+pub(crate) struct LoadReport {
+    /// No config existed, so a default one was written.
+    pub(crate) first_run: bool,
+    pub(crate) failures: Vec<LoadFailure>,
+}
+
+impl LoadReport {
+    /// Records why the file at `path` couldn't be loaded and moves it aside,
+    /// returning whether a fresh file may be written in its place.
+    pub(super) fn back_up(&mut self, path: &Path, error: LoadError) -> bool {
+        log::error!("Failed to load {}: {error}", path.display());
+        let backup = match back_up(path) {
+            Ok(backup) => {
+                log::warn!("Moved {} to {}", path.display(), backup.display());
+                Some(backup)
+            }
+            Err(err) => {
+                log::error!("Failed to back up {}: {err}", path.display());
+                None
+            }
+        };
+        let is_moved = backup.is_some();
+        self.failures.push(LoadFailure {
+            path: path.to_path_buf(),
+            error,
+            backup,
+        });
+        is_moved
+    }
+}
+
+/// Reads the RON file at `path`.
+pub(super) fn read_ron<T: DeserializeOwned>(path: &Path) -> Result<T, LoadError> {
+    let bytes = fs::read(path)?;
+    let text = String::from_utf8(bytes).map_err(|_| LoadError::Encoding)?;
+    Ok(ron::from_str(&text)?)
+}
+
+/// Renames the file at `path` to the first of `<path>.bak`, `<path>.1.bak`,
+/// ... that doesn't exist yet.
+fn back_up(path: &Path) -> io::Result<PathBuf> {
+    let backup = (0..)
+        .map(|n| {
+            let mut name = path.as_os_str().to_owned();
+            if n > 0 {
+                name.push(format!(".{n}"));
+            }
+            name.push(".bak");
+            PathBuf::from(name)
+        })
+        .find(|backup| !backup.exists())
+        .unwrap();
+    fs::rename(path, &backup)?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broken.ron");
+        fs::write(&path, "(\n    name: \"a\",\n    count: ,\n)").unwrap();
+
+        let err = read_ron::<ron::Value>(&path).unwrap_err();
+        assert!(err.to_string().starts_with("Line 3, column 11: "));
+        let LoadError::Parse { line, column, .. } = err else {
+            panic!("expected a parse error, got {err}");
+        };
+        assert_eq!((line, column), (3, 11));
+    }
+
+    #[test]
+    fn tells_missing_and_binary_files_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.ron");
+        assert!(read_ron::<ron::Value>(&missing).unwrap_err().is_not_found());
+
+        let binary = dir.path().join("binary.ron");
+        fs::write(&binary, [0xff, 0xfe, 0x00]).unwrap();
+        let err = read_ron::<ron::Value>(&binary).unwrap_err();
+        assert!(matches!(err, LoadError::Encoding));
+        assert!(!err.is_not_found());
+    }
+
+    #[test]
+    fn backs_up_next_to_earlier_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.ron");
+
+        for expected in ["config.ron.bak", "config.ron.1.bak", "config.ron.2.bak"] {
+            fs::write(&path, expected).unwrap();
+            let backup = back_up(&path).unwrap();
+            assert_eq!(backup, dir.path().join(expected));
+            assert_eq!(fs::read_to_string(&backup).unwrap(), expected);
+            assert!(!path.exists());
+        }
+    }
+
+    #[test]
+    fn records_failures_with_their_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dialog.ron");
+        fs::write(&path, "(").unwrap();
+        let missing = dir.path().join("missing.ron");
+
+        let mut report = LoadReport::default();
+        let err = read_ron::<ron::Value>(&path).unwrap_err();
+        assert!(report.back_up(&path, err));
+        assert!(!report.back_up(&missing, LoadError::Encoding));
+
+        let [moved, kept] = &report.failures[..] else {
+            panic!("expected two failures");
+        };
+        assert_eq!(moved.path, path);
+        assert_eq!(moved.backup, Some(dir.path().join("dialog.ron.bak")));
+        assert!(matches!(moved.error, LoadError::Parse { .. }));
+        assert_eq!(kept.path, missing);
+        assert_eq!(kept.backup, None);
+        assert!(!report.first_run);
+    }
+}
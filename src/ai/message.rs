@@ -0,0 +1,98 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize, de::IgnoredAny};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    /// The role name used by chat completion APIs.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Message {
+    pub(crate) role: Role,
+    pub(crate) content: String,
+    /// Whether the user stopped this answer before it was complete.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) interrupted: bool,
+    /// When the message was written, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) created: Option<u64>,
+    /// The model that wrote the answer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) model: Option<String>,
+}
+
+/// A message of a `dialog.ron` written before the app had its own message
+/// type, when it stored the messages of the `deepseek-api` crate.
+#[derive(Deserialize)]
+pub(super) enum LegacyMessage {
+    System(LegacyContent),
+    User(LegacyContent),
+    Assistant(LegacyContent),
+    Tool(IgnoredAny),
+}
+
+/// The part of a [`LegacyMessage`] that is kept. Names and the other fields
+/// are ignored.
+#[derive(Deserialize)]
+pub(super) struct LegacyContent {
+    #[serde(default)]
+    content: String,
+}
+
+impl LegacyMessage {
+    /// The message in the current format, `None` for tool results, which the
+    /// app never sends.
+    pub(super) fn into_message(self) -> Option<Message> {
+        let (role, LegacyContent { content }) = match self {
+            LegacyMessage::System(content) => (Role::System, content),
+            LegacyMessage::User(content) => (Role::User, content),
+            LegacyMessage::Assistant(content) => (Role::Assistant, content),
+            LegacyMessage::Tool(_) => return None,
+        };
+        Some(Message {
+            created: None,
+            ..Message::new(role, &content)
+        })
+    }
+}
+
+impl Message {
+    pub(crate) fn system(content: &str) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    pub(crate) fn user(content: &str) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    pub(crate) fn assistant(content: &str) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    pub(crate) fn new(role: Role, content: &str) -> Self {
+        Self {
+            role,
+            content: content.to_string(),
+            interrupted: false,
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|duration| duration.as_secs()),
+            model: None,
+        }
+    }
+}
@@ -1,29 +1,108 @@
+mod backend;
+mod branches;
 mod chat;
 mod config;
+mod conversations;
 mod dialog;
+mod error;
+mod import;
+mod load;
+mod message;
+mod reload;
+mod search;
 
-pub(crate) use chat::{ReceiveMessage, SendMessage};
-pub(crate) use config::Config;
+pub(crate) use backend::Provider;
+pub(crate) use branches::{BranchSwitched, DeleteMessage, EditMessage, Regenerate, SelectBranch};
+pub(crate) use chat::{
+    AnswerAdded, MessageSent, PendingMessages, ReceiveMessage, SendMessage, StopGeneration,
+    StreamReceiver,
+};
+pub(crate) use config::{CONFIG_PATH, Config};
+pub(crate) use conversations::{
+    ConversationInfo, ConversationSwitched, Conversations, DeleteConversation, NewConversation,
+    RenameConversation, ShowMessage, SwitchConversation,
+};
 pub(crate) use dialog::Dialog;
+pub(crate) use error::ChatError;
+pub(crate) use import::{ConversationsImported, ImportConversations};
+pub(crate) use load::{LoadFailure, LoadReport};
+pub(crate) use message::{Message, Role};
+pub(crate) use search::{SearchResult, find_all, search};
 
 use bevy::prelude::*;
 
+use branches::on_branch_action;
 use chat::{TokioRuntime, on_send_message, read_stream};
+use conversations::{on_conversation_action, update_conversations};
+use import::{ImportTasks, finish_imports, on_import};
+use reload::{RonLoader, WatchedFiles, apply_reloaded_files, watch_current_dialog};
+
+/// The systems changing the [`Dialog`] in `Update`.
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct DialogSystems;
 
 pub(crate) struct AiPlugin;
 
 impl Plugin for AiPlugin {
     fn build(&self, app: &mut App) {
-        let config = Config::get_or_init();
-        let dialog = Dialog::get_or_init();
+        let mut load_report = LoadReport::default();
+        let config = Config::get_or_init(&mut load_report);
+        let dialog = Dialog::get_or_init(&mut load_report);
+        let conversations = Conversations::load();
 
         let runtime = tokio::runtime::Runtime::new().unwrap();
 
+        app.init_asset::<Config>()
+            .init_asset::<Dialog>()
+            .init_asset_loader::<RonLoader<Config>>()
+            .init_asset_loader::<RonLoader<Dialog>>();
+        let watched_files = WatchedFiles::new(app.world().resource::<AssetServer>(), &dialog);
+
         app.insert_resource(config)
             .insert_resource(dialog)
+            .insert_resource(conversations)
             .insert_resource(TokioRuntime(runtime))
+            .insert_resource(watched_files)
+            .insert_resource(load_report)
+            .init_resource::<PendingMessages>()
+            .init_resource::<ImportTasks>()
             .add_message::<SendMessage>()
             .add_message::<ReceiveMessage>()
-            .add_systems(FixedUpdate, (on_send_message, read_stream));
+            .add_message::<MessageSent>()
+            .add_message::<AnswerAdded>()
+            .add_message::<StopGeneration>()
+            .add_message::<NewConversation>()
+            .add_message::<SwitchConversation>()
+            .add_message::<RenameConversation>()
+            .add_message::<DeleteConversation>()
+            .add_message::<ShowMessage>()
+            .add_message::<ConversationSwitched>()
+            .add_message::<EditMessage>()
+            .add_message::<Regenerate>()
+            .add_message::<DeleteMessage>()
+            .add_message::<SelectBranch>()
+            .add_message::<BranchSwitched>()
+            .add_message::<ImportConversations>()
+            .add_message::<ConversationsImported>()
+            .add_systems(FixedUpdate, (on_send_message, read_stream))
+            .add_systems(
+                Update,
+                (
+                    on_conversation_action,
+                    on_branch_action,
+                    on_import,
+                    finish_imports,
+                    update_conversations,
+                )
+                    .chain()
+                    .in_set(DialogSystems),
+            )
+            .add_systems(
+                Update,
+                (
+                    watch_current_dialog.after(DialogSystems),
+                    apply_reloaded_files.before(DialogSystems),
+                ),
+            );
     }
 }
@@ -0,0 +1,123 @@
+use std::{io, marker::PhantomData};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+};
+use serde::de::DeserializeOwned;
+
+use super::{
+    BranchSwitched, ConversationSwitched, Dialog, StreamReceiver,
+    config::{CONFIG_PATH, Config},
+    error::LoadError,
+};
+
+/// Loads RON files as assets of type `A`.
+#[derive(TypePath)]
+pub(crate) struct RonLoader<A>(PhantomData<fn() -> A>);
+
+impl<A> Default for RonLoader<A> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<A: Asset + DeserializeOwned> AssetLoader for RonLoader<A> {
+    type Asset = A;
+    type Settings = ();
+    type Error = io::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<A, io::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        ron::de::from_bytes(&bytes).map_err(|err| LoadError::from(err).into())
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
+
+/// The config and the file of the current conversation, which the asset
+/// server reloads when they are edited while the app runs.
+#[derive(Resource)]
+pub(crate) struct WatchedFiles {
+    config: Handle<Config>,
+    dialog: Handle<Dialog>,
+}
+
+impl WatchedFiles {
+    pub(crate) fn new(asset_server: &AssetServer, dialog: &Dialog) -> Self {
+        Self {
+            config: asset_server.load(CONFIG_PATH),
+            dialog: asset_server.load(Dialog::path(&dialog.id)),
+        }
+    }
+}
+
+/// Watches the file of the conversation that was opened instead of the old
+/// one.
+pub(crate) fn watch_current_dialog(
+    mut conversation_switched: MessageReader<ConversationSwitched>,
+    dialog: Res<Dialog>,
+    asset_server: Res<AssetServer>,
+    mut watched_files: ResMut<WatchedFiles>,
+) {
+    if conversation_switched.read().count() == 0 {
+        return;
+    }
+    watched_files.dialog = asset_server.load(Dialog::path(&dialog.id));
+}
+
+/// Applies a reloaded config to the next request, and a changed system prompt
+/// to the current conversation.
+///
+/// Our own saves of the conversation are reloaded too, which changes nothing
+/// as the file then matches the [`Dialog`].
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn apply_reloaded_files(
+    mut config_events: MessageReader<AssetEvent<Config>>,
+    mut dialog_events: MessageReader<AssetEvent<Dialog>>,
+    mut branch_switched: MessageWriter<BranchSwitched>,
+    watched_files: Res<WatchedFiles>,
+    configs: Res<Assets<Config>>,
+    dialogs: Res<Assets<Dialog>>,
+    mut config: ResMut<Config>,
+    mut dialog: ResMut<Dialog>,
+    mut stream_receiver: Option<ResMut<StreamReceiver>>,
+) {
+    for event in config_events.read() {
+        if let AssetEvent::Modified { id } = *event
+            && id == watched_files.config.id()
+            && let Some(reloaded) = configs.get(id)
+        {
+            *config = reloaded.clone();
+            log::info!("Reloaded {CONFIG_PATH}");
+        }
+    }
+
+    for event in dialog_events.read() {
+        if let AssetEvent::Modified { id } = *event
+            && id == watched_files.dialog.id()
+            && let Some(prompt) = dialogs.get(id).and_then(Dialog::system_prompt)
+            && dialog.system_prompt() != Some(prompt)
+        {
+            let had_prompt = dialog.system_prompt().is_some();
+            let prompt = prompt.to_string();
+            dialog.set_system_prompt(&prompt);
+            // The new first message shifts the indices of all the others.
+            if !had_prompt {
+                if let Some(stream_receiver) = &mut stream_receiver {
+                    stream_receiver.system_prompt_inserted();
+                }
+                branch_switched.write(BranchSwitched);
+            }
+            log::info!("Reloaded the system prompt of conversation {}", dialog.id);
+        }
+    }
+}
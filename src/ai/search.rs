@@ -0,0 +1,201 @@
+use std::ops::Range;
+
+use unicode_normalization::char::decompose_canonical;
+
+use super::{Dialog, Role};
+
+/// How many characters of context a snippet shows around the match.
+const SNIPPET_BEFORE: usize = 16;
+const SNIPPET_AFTER: usize = 48;
+
+/// A message containing the searched text.
+pub(crate) struct SearchResult {
+    pub(crate) conversation: String,
+    pub(crate) title: String,
+    /// The index of the message in its dialog.
+    pub(crate) index: usize,
+    pub(crate) role: Role,
+    /// The text around the match, split into the part before it, the match
+    /// and the part after it.
+    pub(crate) snippet: [String; 3],
+}
+
+/// Finds `query` in the messages of every stored conversation, in every
+/// branch, most recently modified conversation first.
+///
+/// Matching ignores case, accents and the difference between full-width and
+/// ASCII characters, and finds the query anywhere in the text, so it also works
+/// for languages without spaces between words.
+pub(crate) fn search(query: &str) -> Vec<SearchResult> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let mut results = Vec::new();
+    for (id, _) in Dialog::list() {
+        let dialog = match Dialog::load(&id) {
+            Ok(dialog) => dialog,
+            Err(err) => {
+                log::warn!("Failed to search conversation {id}: {err}");
+                continue;
+            }
+        };
+        let title = dialog.title();
+        for (index, message) in dialog.all_messages() {
+            if message.role == Role::System {
+                continue;
+            }
+            if let Some(range) = find(&message.content, query) {
+                results.push(SearchResult {
+                    conversation: id.clone(),
+                    title: title.clone(),
+                    index,
+                    role: message.role,
+                    snippet: snippet(&message.content, range),
+                });
+            }
+        }
+    }
+    results
+}
+
+/// The byte ranges of the matches of `query` in `text`, compared like in
+/// [`search`].
+pub(crate) fn find_all(text: &str, query: &str) -> Vec<Range<usize>> {
+    let mut matches = Vec::new();
+    if query.is_empty() {
+        return matches;
+    }
+    let mut start = 0;
+    while let Some(range) = find(&text[start..], query) {
+        start += range.end;
+        matches.push(start - range.len()..start);
+    }
+    matches
+}
+
+/// The form characters are compared in: lowercase ASCII for full-width
+/// forms, and without the accents of Latin, Greek and Cyrillic letters.
+fn fold(c: char) -> impl Iterator<Item = char> {
+    let c = match c {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        '\u{3000}' => ' ',
+        _ => c,
+    };
+    let mut decomposed = Vec::new();
+    decompose_canonical(c, |c| decomposed.push(c));
+    decomposed
+        .into_iter()
+        .filter(|c| !('\u{300}'..='\u{36F}').contains(c))
+        .flat_map(char::to_lowercase)
+}
+
+/// The byte range of the first match of `query` in `text`.
+fn find(text: &str, query: &str) -> Option<Range<usize>> {
+    let query: String = query.chars().flat_map(fold).collect();
+    text.char_indices().find_map(|(start, _)| {
+        let mut rest = query.as_str();
+        for (offset, c) in text[start..].char_indices() {
+            if rest.is_empty() {
+                return Some(start..start + offset);
+            }
+            for folded in fold(c) {
+                rest = rest.strip_prefix(folded)?;
+            }
+        }
+        rest.is_empty().then_some(start..text.len())
+    })
+}
+
+/// The match with a little of the text around it, on one line.
+fn snippet(text: &str, range: Range<usize>) -> [String; 3] {
+    let before_chars: Vec<char> = text[..range.start].chars().collect();
+    let mut before = String::new();
+    if before_chars.len() > SNIPPET_BEFORE {
+        before.push('…');
+    }
+    before.extend(&before_chars[before_chars.len() - SNIPPET_BEFORE.min(before_chars.len())..]);
+
+    let mut after: String = text[range.end..].chars().take(SNIPPET_AFTER).collect();
+    if text[range.end..].chars().nth(SNIPPET_AFTER).is_some() {
+        after.push('…');
+    }
+
+    [
+        collapse_whitespace(&before).trim_start().to_string(),
+        collapse_whitespace(&text[range]),
+        collapse_whitespace(&after).trim_end().to_string(),
+    ]
+}
+
+/// Joins the lines of `text`, replacing every run of whitespace with a space.
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::new();
+    for c in text.chars() {
+        if !c.is_whitespace() {
+            collapsed.push(c);
+        } else if !collapsed.ends_with(' ') {
+            collapsed.push(' ');
+        }
+    }
+    collapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_case_accents_and_width() {
+        assert_eq!(find("Say HELLO", "hello"), Some(4..9));
+        assert_eq!(find("Un café crème", "CAFE CREME"), Some(3..15));
+        assert_eq!(find("Ｒｕｓｔ　２０２４", "rust 2024"), Some(0..27));
+        assert_eq!(find("naïve", "naive"), Some(0..6));
+        assert_eq!(find("résumé", "résumé"), Some(0..8));
+        assert_eq!(find("hello", "help"), None);
+    }
+
+    #[test]
+    fn keeps_kana_marks() {
+        assert_eq!(find("かがみ", "がみ"), Some(3..9));
+        assert_eq!(find("かみ", "がみ"), None);
+    }
+
+    #[test]
+    fn folds_characters() {
+        assert_eq!(fold('É').collect::<String>(), "e");
+        assert_eq!(fold('Ａ').collect::<String>(), "a");
+        assert_eq!(fold('\u{3000}').collect::<String>(), " ");
+        assert_eq!(fold('中').collect::<String>(), "中");
+    }
+
+    #[test]
+    fn finds_every_match() {
+        assert_eq!(find_all("Aa aa", "aa"), [0..2, 3..5]);
+        assert_eq!(find_all("ééé", "e"), [0..2, 2..4, 4..6]);
+        assert!(find_all("text", "").is_empty());
+    }
+
+    #[test]
+    fn cuts_snippets_at_characters() {
+        let text = "一二三四五六七八九十".repeat(10);
+        let start = text.char_indices().nth(50).unwrap().0;
+        let range = start..start + "一".len();
+
+        let [before, matched, after] = snippet(&text, range);
+        assert_eq!(before.chars().count(), SNIPPET_BEFORE + 1);
+        assert!(before.starts_with('…'));
+        assert_eq!(matched, "一");
+        assert_eq!(after.chars().count(), SNIPPET_AFTER + 1);
+        assert!(after.ends_with('…'));
+    }
+
+    #[test]
+    fn keeps_short_snippets_whole_on_one_line() {
+        let text = "line\n\n  the match\tand more";
+        let range = find(text, "match").unwrap();
+
+        assert_eq!(snippet(text, range), ["line the ", "match", " and more"]);
+    }
+}
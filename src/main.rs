@@ -3,7 +3,11 @@
 
 mod ai;
 mod ui;
+mod ui_input;
 mod ui_scroll;
+mod ui_select;
+
+use std::env;
 
 use bevy::{
     feathers::{FeathersPlugins, dark_theme::create_dark_theme, theme::UiTheme},
@@ -13,24 +17,39 @@ use bevy::{
 
 use ai::AiPlugin;
 use ui::UiPlugin;
+use ui_input::UiInputPlugin;
 use ui_scroll::UiScrollPlugin;
+use ui_select::UiSelectPlugin;
 
 fn main() {
     let mut app = App::new();
     app.add_plugins((
-        DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                title: "Bevy AI ChatBox".to_string(),
-                resolution: WindowResolution::new(480, 720),
-                position: WindowPosition::Centered(MonitorSelection::Primary),
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: Some(Window {
+                    title: "Bevy AI ChatBox".to_string(),
+                    resolution: WindowResolution::new(760, 720),
+                    position: WindowPosition::Centered(MonitorSelection::Primary),
+                    ..default()
+                }),
+                ..default()
+            })
+            .set(AssetPlugin {
+                // The config and conversations are loaded as assets from the
+                // working directory, where they are also written, and
+                // reloaded when they are edited.
+                file_path: env::current_dir()
+                    .map(|dir| dir.display().to_string())
+                    .unwrap_or_else(|_| ".".to_string()),
+                watch_for_changes_override: Some(true),
                 ..default()
             }),
-            ..default()
-        }),
         FeathersPlugins,
         AiPlugin,
         UiPlugin,
         UiScrollPlugin,
+        UiInputPlugin,
+        UiSelectPlugin,
     ));
 
     app.insert_resource(UiTheme(create_dark_theme())).run();
@@ -0,0 +1,149 @@
+use bevy::{
+    feathers::{
+        constants::fonts,
+        controls::{ButtonProps, button},
+        theme::ThemedText,
+    },
+    prelude::*,
+    text::LineHeight,
+    ui_widgets::{Activate, observe},
+};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+use super::{BUBBLE_BACKGROUND_COLOR, NOTE_TEXT_COLOR, TEXT_COLOR};
+use crate::{ui_input::set_clipboard_text, ui_select::SelectableText};
+
+pub(super) const CODE_BACKGROUND_COLOR: Color =
+    Color::Srgba(Srgba::rgb(0.11372549, 0.11372549, 0.12156863));
+const THEME_NAME: &str = "base16-ocean.dark";
+
+/// Syntaxes, colors and font for fenced code blocks.
+#[derive(Resource)]
+pub(super) struct CodeHighlighter {
+    syntax_set: SyntaxSet,
+    theme: Theme,
+    font: Handle<Font>,
+}
+
+impl FromWorld for CodeHighlighter {
+    fn from_world(world: &mut World) -> Self {
+        let mut themes = ThemeSet::load_defaults().themes;
+        Self {
+            syntax_set: SyntaxSet::load_defaults_newlines(),
+            theme: themes.remove(THEME_NAME).unwrap(),
+            font: world.resource::<AssetServer>().load(fonts::MONO),
+        }
+    }
+}
+
+impl CodeHighlighter {
+    /// Splits `code` into colored pieces, or one plain piece if `language`
+    /// isn't known.
+    fn highlight(&self, code: &str, language: &str) -> Vec<(Color, String)> {
+        let Some(syntax) = self.syntax_set.find_syntax_by_token(language) else {
+            return vec![(TEXT_COLOR, code.to_string())];
+        };
+
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        let mut pieces = Vec::new();
+        for line in LinesWithEndings::from(code) {
+            match highlighter.highlight_line(line, &self.syntax_set) {
+                Ok(ranges) => pieces.extend(ranges.into_iter().map(|(style, text)| {
+                    let color = style.foreground;
+                    (
+                        Color::srgba_u8(color.r, color.g, color.b, color.a),
+                        text.to_string(),
+                    )
+                })),
+                Err(err) => {
+                    log::warn!("Failed to highlight {language} code: {err}");
+                    return vec![(TEXT_COLOR, code.to_string())];
+                }
+            }
+        }
+        pieces
+    }
+
+    /// A panel showing `code` with its language and a copy button. Long lines
+    /// scroll horizontally.
+    pub(super) fn code_block(&self, language: &str, code: &str) -> impl Bundle + use<> {
+        let code = code.trim_end_matches('\n').to_string();
+        let font = TextFont {
+            font: self.font.clone(),
+            font_size: 14.,
+            ..default()
+        };
+        let pieces = self.highlight(&code, language);
+        let label = if language.is_empty() {
+            "text".to_string()
+        } else {
+            language.to_string()
+        };
+
+        (
+            Node {
+                flex_direction: FlexDirection::Column,
+                border_radius: BorderRadius::all(px(8)),
+                overflow: Overflow::clip(),
+                ..default()
+            },
+            BackgroundColor(CODE_BACKGROUND_COLOR),
+            children![
+                (
+                    Node {
+                        justify_content: JustifyContent::SpaceBetween,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::axes(px(12), px(4)),
+                        ..default()
+                    },
+                    BackgroundColor(BUBBLE_BACKGROUND_COLOR),
+                    children![
+                        (
+                            Text::new(label),
+                            TextColor(NOTE_TEXT_COLOR),
+                            TextFont::from_font_size(12.),
+                            LineHeight::Px(18.),
+                        ),
+                        (
+                            button(
+                                ButtonProps::default(),
+                                (),
+                                Spawn((Text::new("Copy"), ThemedText))
+                            ),
+                            observe(move |_event: On<Activate>| {
+                                set_clipboard_text(&code);
+                            })
+                        ),
+                    ],
+                ),
+                (
+                    Node {
+                        padding: UiRect::axes(px(12), px(8)),
+                        overflow: Overflow::scroll_x(),
+                        ..default()
+                    },
+                    children![(
+                        Text::default(),
+                        SelectableText,
+                        TextLayout::new_with_no_wrap(),
+                        font.clone(),
+                        LineHeight::Px(20.),
+                        Children::spawn(SpawnIter(pieces.into_iter().map(move |(color, text)| {
+                            (
+                                TextSpan(text),
+                                TextColor(color),
+                                font.clone(),
+                                LineHeight::Px(20.),
+                            )
+                        }))),
+                    )],
+                ),
+            ],
+        )
+    }
+}
@@ -0,0 +1,137 @@
+use bevy::{
+    feathers::{controls::button, theme::ThemedText},
+    input_focus::InputFocus,
+    prelude::*,
+    ui_widgets::{Activate, observe},
+};
+
+use super::{BUBBLE_BACKGROUND_COLOR, ChatInput};
+use crate::{
+    ai::{self, DeleteMessage, Regenerate},
+    ui_input::{TextInput, set_clipboard_text},
+};
+
+/// The index in the dialog of the message shown by a message box.
+#[derive(Component, Clone, Copy)]
+pub(super) struct StoredMessage(pub(super) usize);
+
+/// Covers the window while a menu is open, so that any click closes it.
+#[derive(Component)]
+pub(super) struct ContextMenu;
+
+/// Opens the menu of a stored message where it was right-clicked.
+pub(super) fn open_context_menu(
+    click: On<Pointer<Click>>,
+    stored_messages: Query<&StoredMessage>,
+    context_menus: Query<(), With<ContextMenu>>,
+    mut commands: Commands,
+) {
+    if click.button != PointerButton::Secondary || !context_menus.is_empty() {
+        return;
+    }
+    let Ok(&StoredMessage(index)) = stored_messages.get(click.entity) else {
+        return;
+    };
+    commands.spawn(context_menu(index, click.pointer_location.position));
+}
+
+fn context_menu(index: usize, position: Vec2) -> impl Bundle {
+    popup_menu(
+        position,
+        children![
+            (
+                menu_item("Copy"),
+                observe(move |_event: On<Activate>, dialog: Res<ai::Dialog>| {
+                    set_clipboard_text(&dialog.message(index).content);
+                })
+            ),
+            (
+                menu_item("Quote"),
+                observe(
+                    move |_event: On<Activate>,
+                          dialog: Res<ai::Dialog>,
+                          mut chat_input: Query<(Entity, &mut TextInput), With<ChatInput>>,
+                          mut input_focus: ResMut<InputFocus>| {
+                        let Ok((entity, mut text_input)) = chat_input.single_mut() else {
+                            return;
+                        };
+                        let text = quote(text_input.text(), &dialog.message(index).content);
+                        text_input.set_text(&text);
+                        input_focus.set(entity);
+                    }
+                )
+            ),
+            (
+                menu_item("Regenerate from here"),
+                observe(
+                    move |_event: On<Activate>, mut regenerate: MessageWriter<Regenerate>| {
+                        regenerate.write(Regenerate(index));
+                    }
+                )
+            ),
+            (
+                menu_item("Delete"),
+                observe(
+                    move |_event: On<Activate>,
+                          mut delete_message: MessageWriter<DeleteMessage>| {
+                        delete_message.write(DeleteMessage(index));
+                    }
+                )
+            ),
+        ],
+    )
+}
+
+/// A menu of `items` with its top-left corner at `position`, in logical
+/// pixels. It closes on the next click, inside or outside of it.
+pub(super) fn popup_menu(position: Vec2, items: impl Bundle) -> impl Bundle {
+    (
+        ContextMenu,
+        Node {
+            position_type: PositionType::Absolute,
+            width: percent(100),
+            height: percent(100),
+            ..default()
+        },
+        GlobalZIndex(1),
+        // Clicks on the items bubble up here too.
+        observe(|click: On<Pointer<Click>>, mut commands: Commands| {
+            commands.entity(click.entity).despawn();
+        }),
+        children![(
+            Node {
+                position_type: PositionType::Absolute,
+                left: px(position.x),
+                top: px(position.y),
+                min_width: px(160),
+                flex_direction: FlexDirection::Column,
+                row_gap: px(2),
+                padding: UiRect::all(px(4)),
+                border_radius: BorderRadius::all(px(8)),
+                ..default()
+            },
+            BackgroundColor(BUBBLE_BACKGROUND_COLOR),
+            items,
+        )],
+    )
+}
+
+pub(super) fn menu_item(label: &str) -> impl Bundle + use<> {
+    button(default(), (), Spawn((Text::new(label), ThemedText)))
+}
+
+/// Appends `content` to `text` as a Markdown block quote, followed by an empty
+/// line to type the reply in.
+fn quote(text: &str, content: &str) -> String {
+    let mut quoted = text.trim_end().to_string();
+    if !quoted.is_empty() {
+        quoted.push_str("\n\n");
+    }
+    for line in content.lines() {
+        quoted.push_str("> ");
+        quoted.push_str(line);
+        quoted.push('\n');
+    }
+    quoted.push('\n');
+    quoted
+}
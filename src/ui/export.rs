@@ -0,0 +1,504 @@
+use std::{
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag, TagEnd};
+use serde::Serialize;
+
+use super::{
+    BACKGROUND_COLOR, BUBBLE_BACKGROUND_COLOR, NOTE_TEXT_COLOR, TEXT_COLOR,
+    code::CODE_BACKGROUND_COLOR,
+    markdown::{CODE_TEXT_COLOR, LINK_COLOR, OPTIONS, RULE_COLOR},
+};
+use crate::ai::{self, Message, Role};
+
+/// The directory exported conversations are written to.
+const EXPORTS_DIR: &str = "exports";
+
+#[derive(Clone, Copy)]
+pub(super) enum ExportFormat {
+    Markdown,
+    Html,
+    /// The `messages` array of an OpenAI-compatible chat completion request,
+    /// with the model and creation time of each message when known.
+    Json,
+}
+
+impl ExportFormat {
+    pub(super) const ALL: [ExportFormat; 3] = [
+        ExportFormat::Markdown,
+        ExportFormat::Html,
+        ExportFormat::Json,
+    ];
+
+    pub(super) fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Html => "HTML",
+            ExportFormat::Json => "JSON",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// Writes the shown branch of `dialog` to [`EXPORTS_DIR`] and returns the path
+/// of the file. Every export gets its own file.
+pub(super) fn export(dialog: &ai::Dialog, format: ExportFormat) -> io::Result<PathBuf> {
+    let title = dialog.title();
+    let messages: Vec<_> = dialog.messages().collect();
+    let content = match format {
+        ExportFormat::Markdown => to_markdown(&title, &messages),
+        ExportFormat::Html => to_html(&title, &messages),
+        ExportFormat::Json => to_json(&messages)?,
+    };
+
+    fs::create_dir_all(EXPORTS_DIR)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    write_new(
+        Path::new(EXPORTS_DIR),
+        |attempt| file_name(&title, &dialog.id, now, attempt, format),
+        &content,
+    )
+}
+
+/// Writes `content` to a new file in `dir` named by `name`, trying the names
+/// of attempts 0, 1, ... until one isn't taken.
+fn write_new(dir: &Path, name: impl Fn(usize) -> String, content: &str) -> io::Result<PathBuf> {
+    let mut attempt = 0;
+    loop {
+        let path = dir.join(name(attempt));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                file.write_all(content.as_bytes())?;
+                return Ok(path);
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// `<title>-<id>-<UTC date and time>.<extension>`, with anything but letters
+/// and digits in the title replaced by dashes. Attempts after the first add
+/// `-1`, `-2`, ... before the extension.
+fn file_name(title: &str, id: &str, exported: u64, attempt: usize, format: ExportFormat) -> String {
+    let mut stem = String::new();
+    for c in title.chars() {
+        if c.is_alphanumeric() {
+            stem.push(c);
+        } else if !stem.is_empty() && !stem.ends_with('-') {
+            stem.push('-');
+        }
+    }
+    if !stem.is_empty() && !stem.ends_with('-') {
+        stem.push('-');
+    }
+    let [year, month, day, hour, minute, second] = date_time(exported);
+    let suffix = if attempt == 0 {
+        String::new()
+    } else {
+        format!("-{attempt}")
+    };
+    format!(
+        "{stem}{id}-{year:04}{month:02}{day:02}-{hour:02}{minute:02}{second:02}{suffix}.{}",
+        format.extension()
+    )
+}
+
+fn role_name(role: Role) -> &'static str {
+    match role {
+        Role::System => "System",
+        Role::User => "User",
+        Role::Assistant => "Assistant",
+    }
+}
+
+/// The role of a message followed by its model and time when known, e.g.
+/// "Assistant · deepseek-chat · 2026-10-18 12:00 UTC".
+fn heading(message: &Message) -> String {
+    let mut heading = role_name(message.role).to_string();
+    if let Some(model) = &message.model {
+        heading.push_str(" · ");
+        heading.push_str(model);
+    }
+    if let Some(created) = message.created {
+        heading.push_str(" · ");
+        heading.push_str(&format_time(created));
+    }
+    heading
+}
+
+/// Formats seconds since the Unix epoch as a UTC date and time.
+fn format_time(seconds: u64) -> String {
+    let [year, month, day, hour, minute, _] = date_time(seconds);
+    format!("{year:04}-{month:02}-{day:02} {hour:02}:{minute:02} UTC")
+}
+
+/// The UTC year, month, day, hour, minute and second of seconds since the
+/// Unix epoch.
+fn date_time(seconds: u64) -> [u64; 6] {
+    // Converts days since the epoch to a civil date, after Howard Hinnant's
+    // `civil_from_days`.
+    let days = seconds / 86400 + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    let time = seconds % 86400;
+    [year, month, day, time / 3600, time / 60 % 60, time % 60]
+}
+
+fn to_markdown(title: &str, messages: &[&Message]) -> String {
+    let mut markdown = format!("# {title}\n");
+    for message in messages {
+        let _ = write!(
+            markdown,
+            "\n## {}\n\n{}\n",
+            heading(message),
+            message.content.trim_end()
+        );
+        if message.interrupted {
+            markdown.push_str("\n*(interrupted)*\n");
+        }
+    }
+    markdown
+}
+
+#[derive(Serialize)]
+struct JsonMessage<'a> {
+    role: &'static str,
+    content: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    /// Seconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<u64>,
+}
+
+/// The messages as a JSON array in the shape of a chat completion request.
+fn to_json(messages: &[&Message]) -> io::Result<String> {
+    let messages: Vec<_> = messages
+        .iter()
+        .map(|message| JsonMessage {
+            role: message.role.as_str(),
+            content: &message.content,
+            model: message.model.as_deref(),
+            created: message.created,
+        })
+        .collect();
+    serde_json::to_string_pretty(&messages).map_err(io::Error::other)
+}
+
+fn css_color(color: Color) -> String {
+    color.to_srgba().to_hex()
+}
+
+/// A page that needs no other files, styled like the app.
+fn to_html(title: &str, messages: &[&Message]) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ margin: 0; background: {background}; color: {text}; font: 16px/1.5 "Noto Sans SC", sans-serif; }}
+main {{ max-width: 800px; margin: 0 auto; padding: 24px 16px; }}
+h1 {{ font-size: 24px; }}
+.message {{ display: flex; flex-direction: column; margin: 24px 0; }}
+.user {{ align-items: flex-end; }}
+.meta {{ color: {note}; font-size: 12px; margin-bottom: 4px; }}
+.bubble {{ max-width: 100%; box-sizing: border-box; padding: 8px 16px; border-radius: 16px; overflow-wrap: anywhere; }}
+.user .bubble, .system .bubble {{ background: {bubble}; white-space: pre-wrap; }}
+.note {{ color: {note}; font-size: 14px; }}
+a {{ color: {link}; }}
+code {{ color: {code_text}; }}
+pre {{ background: {code_background}; padding: 12px; border-radius: 8px; overflow-x: auto; }}
+pre code {{ color: {text}; }}
+blockquote {{ margin: 0; padding-left: 12px; border-left: 4px solid {rule}; color: {note}; }}
+hr {{ border: none; border-top: 1px solid {rule}; }}
+table {{ border-collapse: collapse; }}
+th, td {{ border: 1px solid {rule}; padding: 4px 8px; }}
+</style>
+</head>
+<body>
+<main>
+<h1>{title}</h1>
+"#,
+        title = escape(title),
+        background = css_color(BACKGROUND_COLOR),
+        text = css_color(TEXT_COLOR),
+        note = css_color(NOTE_TEXT_COLOR),
+        bubble = css_color(BUBBLE_BACKGROUND_COLOR),
+        link = css_color(LINK_COLOR),
+        code_text = css_color(CODE_TEXT_COLOR),
+        code_background = css_color(CODE_BACKGROUND_COLOR),
+        rule = css_color(RULE_COLOR),
+    );
+
+    for message in messages {
+        let _ = write!(
+            html,
+            "<section class=\"message {}\">\n<div class=\"meta\">{}</div>\n<div class=\"bubble\">",
+            message.role.as_str(),
+            escape(&heading(message))
+        );
+        if message.role == Role::Assistant {
+            html.push('\n');
+            push_markdown_html(&mut html, &message.content);
+        } else {
+            html.push_str(&escape(&message.content));
+        }
+        html.push_str("</div>\n");
+        if message.interrupted {
+            html.push_str("<div class=\"note\">Interrupted</div>\n");
+        }
+        html.push_str("</section>\n");
+    }
+    html.push_str("</main>\n</body>\n</html>\n");
+    html
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Whether a link to `url` may be kept in the page: web and mail links, and
+/// relative ones. Others, like `javascript:` URLs, could run code.
+fn is_safe_url(url: &str) -> bool {
+    // Browsers ignore these inside the scheme.
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+        .collect();
+    match url.find([':', '/', '?', '#']) {
+        Some(end) if url[end..].starts_with(':') => ["http", "https", "mailto"]
+            .iter()
+            .any(|scheme| url[..end].eq_ignore_ascii_case(scheme)),
+        _ => true,
+    }
+}
+
+/// Renders the Markdown of an answer with the same extensions as the chat.
+/// Raw HTML in it is shown as text, and so are links that aren't
+/// [safe](is_safe_url).
+fn push_markdown_html(html: &mut String, source: &str) {
+    let mut in_table_head = false;
+    // Whether each open link was written as one.
+    let mut links = Vec::new();
+    for event in Parser::new_ext(source, OPTIONS) {
+        match event {
+            Event::Start(tag) => match tag {
+                Tag::Paragraph => html.push_str("<p>"),
+                Tag::Heading { level, .. } => {
+                    let _ = write!(html, "<{level}>");
+                }
+                Tag::BlockQuote(_) => html.push_str("<blockquote>\n"),
+                Tag::CodeBlock(kind) => match kind {
+                    CodeBlockKind::Fenced(language) if !language.is_empty() => {
+                        let _ =
+                            write!(html, "<pre><code class=\"language-{}\">", escape(&language));
+                    }
+                    _ => html.push_str("<pre><code>"),
+                },
+                Tag::List(Some(1)) => html.push_str("<ol>\n"),
+                Tag::List(Some(start)) => {
+                    let _ = writeln!(html, "<ol start=\"{start}\">");
+                }
+                Tag::List(None) => html.push_str("<ul>\n"),
+                Tag::Item => html.push_str("<li>"),
+                Tag::Table(_) => html.push_str("<table>\n"),
+                Tag::TableHead => {
+                    in_table_head = true;
+                    html.push_str("<thead><tr>");
+                }
+                Tag::TableRow => html.push_str("<tr>"),
+                Tag::TableCell => html.push_str(if in_table_head { "<th>" } else { "<td>" }),
+                Tag::Emphasis => html.push_str("<em>"),
+                Tag::Strong => html.push_str("<strong>"),
+                Tag::Strikethrough => html.push_str("<del>"),
+                // Images are linked rather than embedded, as the page should
+                // not load anything.
+                Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                    let is_safe = is_safe_url(&dest_url);
+                    if is_safe {
+                        let _ = write!(html, "<a href=\"{}\">", escape(&dest_url));
+                    }
+                    links.push(is_safe);
+                }
+                _ => {}
+            },
+            Event::End(tag) => match tag {
+                TagEnd::Paragraph => html.push_str("</p>\n"),
+                TagEnd::Heading(level) => {
+                    let _ = writeln!(html, "</{level}>");
+                }
+                TagEnd::BlockQuote(_) => html.push_str("</blockquote>\n"),
+                TagEnd::CodeBlock => html.push_str("</code></pre>\n"),
+                TagEnd::List(true) => html.push_str("</ol>\n"),
+                TagEnd::List(false) => html.push_str("</ul>\n"),
+                TagEnd::Item => html.push_str("</li>\n"),
+                TagEnd::Table => html.push_str("</tbody></table>\n"),
+                TagEnd::TableHead => {
+                    in_table_head = false;
+                    html.push_str("</tr></thead>\n<tbody>\n");
+                }
+                TagEnd::TableRow => html.push_str("</tr>\n"),
+                TagEnd::TableCell => html.push_str(if in_table_head { "</th>" } else { "</td>" }),
+                TagEnd::Emphasis => html.push_str("</em>"),
+                TagEnd::Strong => html.push_str("</strong>"),
+                TagEnd::Strikethrough => html.push_str("</del>"),
+                TagEnd::Link | TagEnd::Image => {
+                    html.push_str(if links.pop().unwrap_or_default() {
+                        "</a>"
+                    } else {
+                        ""
+                    });
+                }
+                _ => {}
+            },
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                html.push_str(&escape(&text));
+            }
+            Event::Code(code) => {
+                let _ = write!(html, "<code>{}</code>", escape(&code));
+            }
+            Event::SoftBreak => html.push('\n'),
+            Event::HardBreak => html.push_str("<br>\n"),
+            Event::Rule => html.push_str("<hr>\n"),
+            Event::TaskListMarker(checked) => html.push_str(if checked {
+                "<input type=\"checkbox\" checked disabled> "
+            } else {
+                "<input type=\"checkbox\" disabled> "
+            }),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_json_as_a_messages_array() {
+        let mut answer = Message::assistant("Hello");
+        answer.model = Some("deepseek-chat".to_string());
+        answer.created = Some(0);
+        let mut question = Message::user("Hi");
+        question.created = None;
+
+        let json = to_json(&[&question, &answer]).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!([
+                { "role": "user", "content": "Hi" },
+                { "role": "assistant", "content": "Hello", "model": "deepseek-chat", "created": 0 },
+            ])
+        );
+    }
+
+    #[test]
+    fn renders_answers_as_html_and_the_rest_as_text() {
+        let mut question = Message::user("<b>Hi</b>");
+        question.created = None;
+        let mut answer = Message::assistant(
+            "**Hello** [docs](https://example.com/?a=1&b=2)\n\n```rust\nfn main() {}\n```",
+        );
+        answer.created = None;
+        answer.interrupted = true;
+
+        let html = to_html("A & B", &[&question, &answer]);
+        assert!(html.contains("<title>A &amp; B</title>"));
+        assert!(html.contains(
+            "<section class=\"message user\">\n<div class=\"meta\">User</div>\n\
+             <div class=\"bubble\">&lt;b&gt;Hi&lt;/b&gt;</div>"
+        ));
+        assert!(html.contains(
+            "<p><strong>Hello</strong> <a href=\"https://example.com/?a=1&amp;b=2\">docs</a></p>"
+        ));
+        assert!(html.contains("<pre><code class=\"language-rust\">fn main() {}\n</code></pre>"));
+        assert!(html.contains("<div class=\"note\">Interrupted</div>"));
+    }
+
+    #[test]
+    fn writes_unsafe_links_as_text() {
+        let answer = Message::assistant(
+            "[a](javascript:alert(1)) [b](JavaScript:alert(1)) ![c](data:text/html,x) \
+             [d](mailto:me@example.com) [e](../page.html)",
+        );
+
+        let html = to_html("Links", &[&answer]);
+        assert!(!html.to_lowercase().contains("javascript"));
+        assert!(!html.contains("data:"));
+        assert!(html.contains("<p>a b c <a href=\"mailto:me@example.com\">d</a> "));
+        assert!(html.contains("<a href=\"../page.html\">e</a></p>"));
+        assert!(!is_safe_url(" java\tscript:alert(1)"));
+        assert!(is_safe_url("#section"));
+        assert!(is_safe_url("path/with:colon"));
+    }
+
+    #[test]
+    fn never_overwrites_an_earlier_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let name = |attempt| file_name("Title", "17", 0, attempt, ExportFormat::Markdown);
+
+        let first = write_new(dir.path(), name, "first").unwrap();
+        let second = write_new(dir.path(), name, "second").unwrap();
+        assert_eq!(first, dir.path().join("Title-17-19700101-000000.md"));
+        assert_eq!(second, dir.path().join("Title-17-19700101-000000-1.md"));
+        assert_eq!(fs::read_to_string(first).unwrap(), "first");
+        assert_eq!(fs::read_to_string(second).unwrap(), "second");
+    }
+
+    #[test]
+    fn names_files_after_the_title_and_time() {
+        assert_eq!(
+            file_name("What's up?", "17", 1_792_324_805, 0, ExportFormat::Markdown),
+            "What-s-up-17-20261018-120005.md"
+        );
+        assert_eq!(
+            file_name("", "17", 0, 2, ExportFormat::Json),
+            "17-19700101-000000-2.json"
+        );
+        assert_eq!(format_time(1_792_324_805), "2026-10-18 12:00 UTC");
+    }
+}
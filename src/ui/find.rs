@@ -0,0 +1,340 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use bevy::{
+    feathers::{controls::button, theme::ThemedText},
+    input_focus::InputFocus,
+    prelude::*,
+    text::{LineHeight, TextLayoutInfo},
+    ui::{UiGlobalTransform, UiStack, widget::TextUiReader},
+    ui_widgets::{Activate, observe},
+};
+
+use super::{BUBBLE_BACKGROUND_COLOR, ChatInput, Dialog, NOTE_TEXT_COLOR};
+use crate::{
+    ai,
+    ui_input::{SingleLine, TextInput, TextInputCancel, TextInputSubmit, text_input},
+    ui_select::{SelectableText, document_order, full_text, range_rects, spawn_highlights},
+};
+
+const MATCH_COLOR: Color = Color::Srgba(Srgba::new(0.9490196, 0.7490196, 0.2627451, 0.3));
+const CURRENT_MATCH_COLOR: Color = Color::Srgba(Srgba::new(0.9490196, 0.5254902, 0.1254902, 0.7));
+
+#[derive(Component)]
+pub(super) struct FindBar;
+
+#[derive(Component)]
+pub(super) struct FindInput;
+
+#[derive(Component)]
+pub(super) struct FindCounter;
+
+/// Marks the nodes drawn over matches, with the text they are in.
+#[derive(Component)]
+pub(super) struct FindHighlight(Entity);
+
+/// The matches of the find bar in the shown messages.
+#[derive(Resource, Default)]
+pub(super) struct Find {
+    is_open: bool,
+    query: String,
+    /// Every match in document order.
+    matches: Vec<(Entity, Range<usize>)>,
+    /// The index in `matches` of the match navigated to.
+    current: usize,
+    /// Whether to scroll the current match into view.
+    reveal: bool,
+    /// The query `found` holds the matches of, `None` while nothing is
+    /// searched.
+    searched: Option<String>,
+    /// The matches in each message text, kept until its layout changes.
+    found: HashMap<Entity, Vec<Range<usize>>>,
+    /// The match highlighted as the current one.
+    drawn_current: Option<(Entity, Range<usize>)>,
+}
+
+impl Find {
+    /// Moves to the next match, or the previous one, wrapping around.
+    fn step(&mut self, forward: bool) {
+        let count = self.matches.len();
+        if count == 0 {
+            return;
+        }
+        self.current = if forward {
+            (self.current + 1) % count
+        } else {
+            (self.current + count - 1) % count
+        };
+        self.reveal = true;
+    }
+}
+
+/// A bar over the top of the dialog, opened with Ctrl+F. Enter and
+/// Shift+Enter move to the next and previous match, Escape closes it.
+pub(super) fn find_bar() -> impl Bundle {
+    (
+        FindBar,
+        Node {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            top: px(8),
+            right: px(24),
+            align_items: AlignItems::Center,
+            column_gap: px(8),
+            padding: UiRect::all(px(4)),
+            border_radius: BorderRadius::all(px(26)),
+            ..default()
+        },
+        BackgroundColor(BUBBLE_BACKGROUND_COLOR),
+        children![
+            (
+                Node {
+                    width: px(220),
+                    ..default()
+                },
+                children![(
+                    FindInput,
+                    SingleLine,
+                    text_input("Find"),
+                    observe(|submit: On<TextInputSubmit>, mut find: ResMut<Find>| {
+                        find.step(!submit.shift);
+                    }),
+                    observe(|_cancel: On<TextInputCancel>, mut find: ResMut<Find>| {
+                        find.is_open = false;
+                    }),
+                )],
+            ),
+            (
+                FindCounter,
+                Text::default(),
+                TextColor(NOTE_TEXT_COLOR),
+                TextFont::from_font_size(12.),
+                LineHeight::Px(18.),
+                Node {
+                    min_width: px(56),
+                    ..default()
+                },
+            ),
+            (
+                button(default(), (), Spawn((Text::new("<"), ThemedText))),
+                observe(|_event: On<Activate>, mut find: ResMut<Find>| {
+                    find.step(false);
+                })
+            ),
+            (
+                button(default(), (), Spawn((Text::new(">"), ThemedText))),
+                observe(|_event: On<Activate>, mut find: ResMut<Find>| {
+                    find.step(true);
+                })
+            ),
+            (
+                button(default(), (), Spawn((Text::new("Close"), ThemedText))),
+                observe(|_event: On<Activate>, mut find: ResMut<Find>| {
+                    find.is_open = false;
+                })
+            ),
+        ],
+    )
+}
+
+/// Opens the find bar with Ctrl+F, selecting the last query.
+pub(super) fn open_find_bar(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut find: ResMut<Find>,
+    mut input: Single<(Entity, &mut TextInput), With<FindInput>>,
+    mut input_focus: ResMut<InputFocus>,
+) {
+    let shortcut = keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ]);
+    if !shortcut || !keys.just_pressed(KeyCode::KeyF) {
+        return;
+    }
+
+    find.is_open = true;
+    find.reveal = true;
+    let (entity, text_input) = &mut *input;
+    text_input.select_all();
+    input_focus.set(*entity);
+}
+
+/// Shows or hides the bar and follows the typed query.
+pub(super) fn update_find_bar(
+    mut find: ResMut<Find>,
+    mut bar: Single<&mut Node, With<FindBar>>,
+    input: Single<(Entity, &TextInput), With<FindInput>>,
+    chat_input: Single<Entity, With<ChatInput>>,
+    mut counter: Single<&mut Text, With<FindCounter>>,
+    mut input_focus: ResMut<InputFocus>,
+) {
+    let (input_entity, input) = *input;
+    let display = if find.is_open {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    if bar.display != display {
+        bar.display = display;
+    }
+    // Typing goes back to the message once the bar closes.
+    if !find.is_open && input_focus.get() == Some(input_entity) {
+        input_focus.set(*chat_input);
+    }
+
+    let query = if find.is_open { input.text() } else { "" };
+    if find.query != query {
+        find.query = query.to_string();
+        find.current = 0;
+        find.reveal = true;
+    }
+
+    let count = find.matches.len();
+    let counter_text = if find.query.is_empty() {
+        String::new()
+    } else if count == 0 {
+        "No results".to_string()
+    } else {
+        format!("{} of {count}", find.current + 1)
+    };
+    if counter.0 != counter_text {
+        counter.0 = counter_text;
+    }
+}
+
+/// Finds the query in the message texts, draws a highlight over every match
+/// and scrolls the current one into view.
+///
+/// Only the texts whose layout changed are searched again, and only the
+/// highlights of texts whose matches changed are drawn again.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn update_find_matches(
+    mut find: ResMut<Find>,
+    texts: Query<(Entity, Ref<TextLayoutInfo>), With<SelectableText>>,
+    parents: Query<&ChildOf>,
+    nodes: Query<(&ComputedNode, &UiGlobalTransform)>,
+    mut dialog: Single<
+        (
+            Entity,
+            &ComputedNode,
+            &UiGlobalTransform,
+            &mut ScrollPosition,
+        ),
+        With<Dialog>,
+    >,
+    highlights: Query<(Entity, &FindHighlight)>,
+    ui_stack: Res<UiStack>,
+    mut reader: TextUiReader,
+    mut commands: Commands,
+) {
+    if !find.is_changed() && !texts.iter().any(|(_, layout)| layout.is_changed()) {
+        return;
+    }
+
+    // Only the texts of the messages are searched.
+    let (dialog_entity, dialog_node, dialog_transform, scroll_position) = &mut *dialog;
+    let order = document_order(&ui_stack);
+    let mut message_texts: Vec<_> = texts
+        .iter()
+        .filter(|(text, _)| {
+            parents
+                .iter_ancestors(*text)
+                .any(|ancestor| ancestor == *dialog_entity)
+        })
+        .filter_map(|(text, layout)| Some((*order.get(&text)?, text, layout)))
+        .collect();
+    message_texts.sort_by_key(|(index, ..)| *index);
+
+    let find = find.bypass_change_detection();
+    // The texts whose highlights are drawn again.
+    let mut redrawn = HashSet::new();
+    let query = Some(find.query.clone()).filter(|query| find.is_open && !query.is_empty());
+    if find.searched != query {
+        find.searched = query;
+        find.found.clear();
+        redrawn.extend(highlights.iter().map(|(_, FindHighlight(text))| *text));
+    }
+    find.found
+        .retain(|text, _| message_texts.iter().any(|(_, other, _)| other == text));
+    if let Some(query) = &find.searched {
+        for (_, text, layout) in &message_texts {
+            if layout.is_changed() || !find.found.contains_key(text) {
+                let content = full_text(&mut reader, *text);
+                find.found.insert(*text, ai::find_all(&content, query));
+                redrawn.insert(*text);
+            }
+        }
+    }
+
+    find.matches = message_texts
+        .iter()
+        .flat_map(|(_, text, _)| {
+            let ranges = find.found.get(text).into_iter().flatten();
+            ranges.map(|range| (*text, range.clone()))
+        })
+        .collect();
+    find.current = find.current.min(find.matches.len().saturating_sub(1));
+    let current = find.matches.get(find.current).cloned();
+    if find.drawn_current != current {
+        redrawn.extend(
+            find.drawn_current
+                .iter()
+                .chain(&current)
+                .map(|(text, _)| *text),
+        );
+        find.drawn_current = current.clone();
+    }
+
+    for (highlight, FindHighlight(text)) in &highlights {
+        if redrawn.contains(text) || !find.found.contains_key(text) {
+            commands.entity(highlight).despawn();
+        }
+    }
+    for (_, text, layout) in &message_texts {
+        if !redrawn.contains(text) {
+            continue;
+        }
+        let content = full_text(&mut reader, *text);
+        for range in find.found.get(text).into_iter().flatten() {
+            let rects = range_rects(&content, range, layout);
+            let is_current = current
+                .as_ref()
+                .is_some_and(|(current_text, current_range)| {
+                    current_text == text && current_range == range
+                });
+            let color = if is_current {
+                CURRENT_MATCH_COLOR
+            } else {
+                MATCH_COLOR
+            };
+            spawn_highlights(*text, &rects, &nodes, &parents, &mut commands, || {
+                (FindHighlight(*text), BackgroundColor(color))
+            });
+        }
+    }
+
+    if find.reveal
+        && let Some((text, range)) = &current
+        && let Some((_, _, layout)) = message_texts.iter().find(|(_, other, _)| other == text)
+    {
+        find.reveal = false;
+        let content = full_text(&mut reader, *text);
+        let rects = range_rects(&content, range, layout);
+        if let (Some(rect), Ok((text_node, text_transform))) = (rects.first(), nodes.get(*text)) {
+            // Bring the match into view when it is outside, showing it a
+            // third of the way down.
+            let dialog_top = dialog_transform.translation.y - dialog_node.size().y / 2.;
+            let text_top = text_transform.translation.y - text_node.size().y / 2.;
+            let top = text_top + rect.min.y - dialog_top;
+            if top < 0. || top + rect.height() > dialog_node.size().y {
+                scroll_position.y = (scroll_position.y
+                    + (top - dialog_node.size().y / 3.) * dialog_node.inverse_scale_factor())
+                .max(0.);
+            }
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use bevy::{prelude::*, window::FileDragAndDrop};
+
+use super::notice::{Notice, show_notice};
+use crate::ai::{ConversationsImported, ImportConversations};
+
+/// Imports the conversations of files dropped on the window.
+pub(super) fn import_dropped_files(
+    mut file_drag_and_drop: MessageReader<FileDragAndDrop>,
+    mut import_conversations: MessageWriter<ImportConversations>,
+) {
+    for event in file_drag_and_drop.read() {
+        if let FileDragAndDrop::DroppedFile { path_buf, .. } = event {
+            import_conversations.write(ImportConversations(path_buf.clone()));
+        }
+    }
+}
+
+/// Tells how many conversations were imported and the first thing that was
+/// left out. The others are in the log.
+pub(super) fn update_conversations_imported(
+    mut conversations_imported: MessageReader<ConversationsImported>,
+    notices: Query<Entity, With<Notice>>,
+    mut commands: Commands,
+) {
+    for ConversationsImported { count, errors } in conversations_imported.read() {
+        let mut text = format!(
+            "Imported {count} conversation{}",
+            if *count == 1 { "" } else { "s" }
+        );
+        if let Some(error) = errors.first() {
+            text.push_str(&format!(
+                ", skipped {} item{}: {error}",
+                errors.len(),
+                if errors.len() == 1 { "" } else { "s" }
+            ));
+        }
+        show_notice(text, &notices, &mut commands);
+    }
+}
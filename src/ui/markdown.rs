@@ -0,0 +1,641 @@
+use std::ops::Range;
+
+use bevy::{
+    feathers::constants::fonts,
+    prelude::*,
+    text::{LineHeight, Strikethrough, Underline},
+};
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+use crate::ui_select::SelectableText;
+
+use super::{BUBBLE_BACKGROUND_COLOR, NOTE_TEXT_COLOR, TEXT_COLOR, code::CodeHighlighter};
+
+pub(super) const LINK_COLOR: Color = Color::Srgba(Srgba::rgb(0.4509804, 0.6627451, 0.9764706));
+/// The color of emphasis in text that the [italic faces](MarkdownFonts) can't
+/// show, e.g. Chinese.
+const EMPHASIS_COLOR: Color = Color::Srgba(Srgba::rgb(0.9647059, 0.827451, 0.6039216));
+/// The color of bold text that the [bold faces](MarkdownFonts) can't show.
+const STRONG_COLOR: Color = Color::Srgba(Srgba::rgb(0.5803922, 0.8862745, 0.7529412));
+pub(super) const CODE_TEXT_COLOR: Color = Color::Srgba(Srgba::rgb(0.9372549, 0.6196078, 0.7294118));
+pub(super) const RULE_COLOR: Color = Color::Srgba(Srgba::rgb(0.2745098, 0.2745098, 0.2901961));
+
+const FONT_SIZE: f32 = 16.;
+const LINE_HEIGHT: f32 = 24.;
+
+/// Assistant message content rendered as Markdown.
+///
+/// The source may grow while the answer streams; only the top-level blocks
+/// that changed are rendered again.
+#[derive(Component, Default)]
+pub(super) struct Markdown {
+    source: String,
+    /// The source of each rendered top-level block, with the node it was
+    /// rendered into.
+    blocks: Vec<(String, Entity)>,
+}
+
+impl Markdown {
+    pub(super) fn push_str(&mut self, content: &str) {
+        self.source.push_str(content);
+    }
+}
+
+pub(super) fn markdown(source: String) -> impl Bundle {
+    (
+        Markdown {
+            source,
+            ..default()
+        },
+        Node {
+            flex_direction: FlexDirection::Column,
+            row_gap: px(8),
+            ..default()
+        },
+    )
+}
+
+/// The bold and italic faces of the Fira Sans font embedded by feathers. The
+/// default font has only a regular face, and Fira Sans has no Chinese, so
+/// these are only used for text they cover.
+#[derive(Resource)]
+pub(super) struct MarkdownFonts {
+    bold: Handle<Font>,
+    italic: Handle<Font>,
+    bold_italic: Handle<Font>,
+}
+
+impl FromWorld for MarkdownFonts {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        Self {
+            bold: asset_server.load(fonts::BOLD),
+            italic: asset_server.load(fonts::ITALIC),
+            bold_italic: asset_server.load(fonts::BOLD_ITALIC),
+        }
+    }
+}
+
+impl MarkdownFonts {
+    /// The face for `text`, `None` if it's regular text or has characters
+    /// outside the Latin, Greek and Cyrillic scripts and punctuation.
+    fn face(&self, text: &str, is_bold: bool, is_italic: bool) -> Option<&Handle<Font>> {
+        let face = match (is_bold, is_italic) {
+            (false, false) => return None,
+            (true, false) => &self.bold,
+            (false, true) => &self.italic,
+            (true, true) => &self.bold_italic,
+        };
+        text.chars()
+            .all(|c| c < '\u{530}' || ('\u{2000}'..'\u{2070}').contains(&c))
+            .then_some(face)
+    }
+}
+
+/// The Markdown extensions answers are rendered with.
+pub(super) const OPTIONS: Options = Options::ENABLE_TABLES
+    .union(Options::ENABLE_STRIKETHROUGH)
+    .union(Options::ENABLE_TASKLISTS)
+    .union(Options::ENABLE_GFM);
+
+/// Splits `source` into its top-level blocks and their events.
+fn top_level_blocks(source: &str) -> Vec<(Range<usize>, Vec<Event<'_>>)> {
+    let mut blocks = Vec::new();
+    let mut depth = 0;
+    for (event, range) in Parser::new_ext(source, OPTIONS).into_offset_iter() {
+        if depth == 0 {
+            blocks.push((range, Vec::new()));
+        }
+        match event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => {}
+        }
+        if let Some((_, events)) = blocks.last_mut() {
+            events.push(event);
+        }
+    }
+    blocks
+}
+
+pub(super) fn update_markdown(
+    mut markdowns: Query<(Entity, &mut Markdown), Changed<Markdown>>,
+    highlighter: Res<CodeHighlighter>,
+    fonts: Res<MarkdownFonts>,
+    mut commands: Commands,
+) {
+    for (entity, mut markdown) in &mut markdowns {
+        // Only the source counts as a change.
+        let Markdown { source, blocks } = markdown.bypass_change_detection();
+        let new_blocks = top_level_blocks(source);
+
+        let unchanged = blocks
+            .iter()
+            .zip(&new_blocks)
+            .take_while(|((old_source, _), (range, _))| *old_source == source[range.clone()])
+            .count();
+        for (_, block) in blocks.drain(unchanged..) {
+            commands.entity(block).despawn();
+        }
+
+        for (range, events) in new_blocks.into_iter().skip(unchanged) {
+            let block = commands
+                .spawn(Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: px(8),
+                    ..default()
+                })
+                .id();
+            Renderer::new(&mut commands, &highlighter, &fonts, block).render(events);
+            commands.entity(entity).add_child(block);
+            blocks.push((source[range].to_string(), block));
+        }
+    }
+}
+
+/// Turns Markdown events into UI nodes.
+struct Renderer<'a, 'w, 's> {
+    commands: &'a mut Commands<'w, 's>,
+    highlighter: &'a CodeHighlighter,
+    fonts: &'a MarkdownFonts,
+    /// The nodes new blocks are added to, innermost last.
+    containers: Vec<Entity>,
+    /// The text node inline content is added to, created on demand.
+    text: Option<Entity>,
+    /// The next number of each open list, or `None` for bullet lists.
+    list_numbers: Vec<Option<u64>>,
+    /// The column alignments of the open table and the current column.
+    table: Option<(Vec<Alignment>, usize)>,
+    is_table_head: bool,
+    heading: Option<HeadingLevel>,
+    /// The language and text of the open code block.
+    code_block: Option<(String, String)>,
+    strong: usize,
+    emphasis: usize,
+    strikethrough: usize,
+    link: usize,
+}
+
+impl<'a, 'w, 's> Renderer<'a, 'w, 's> {
+    fn new(
+        commands: &'a mut Commands<'w, 's>,
+        highlighter: &'a CodeHighlighter,
+        fonts: &'a MarkdownFonts,
+        root: Entity,
+    ) -> Self {
+        Self {
+            commands,
+            highlighter,
+            fonts,
+            containers: vec![root],
+            text: None,
+            list_numbers: Vec::new(),
+            table: None,
+            is_table_head: false,
+            heading: None,
+            code_block: None,
+            strong: 0,
+            emphasis: 0,
+            strikethrough: 0,
+            link: 0,
+        }
+    }
+
+    fn render(mut self, events: Vec<Event>) {
+        for event in events {
+            match event {
+                Event::Start(tag) => self.start(tag),
+                Event::End(tag) => self.end(tag),
+                Event::Text(text) => match &mut self.code_block {
+                    Some((_, code)) => code.push_str(&text),
+                    None => self.push_text(&text),
+                },
+                Event::Code(code) => self.push_code(&code),
+                Event::InlineMath(math) | Event::DisplayMath(math) => self.push_code(&math),
+                Event::Html(html) | Event::InlineHtml(html) => self.push_text(&html),
+                Event::FootnoteReference(name) => self.push_text(&format!("[^{name}]")),
+                Event::SoftBreak => self.push_text(" "),
+                Event::HardBreak => self.push_text("\n"),
+                Event::Rule => {
+                    self.push_block((
+                        Node {
+                            height: px(1),
+                            margin: UiRect::vertical(px(4)),
+                            ..default()
+                        },
+                        BackgroundColor(RULE_COLOR),
+                    ));
+                }
+                Event::TaskListMarker(checked) => {
+                    self.push_text(if checked { "☑ " } else { "☐ " });
+                }
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.text = None,
+            Tag::Heading { level, .. } => {
+                self.heading = Some(level);
+                self.text = None;
+            }
+            Tag::BlockQuote(_) => self.push_container((
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: px(8),
+                    padding: UiRect::left(px(12)),
+                    border: UiRect::left(px(3)),
+                    ..default()
+                },
+                BorderColor::all(RULE_COLOR),
+            )),
+            Tag::CodeBlock(kind) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code_block = Some((language, String::new()));
+            }
+            Tag::List(start) => {
+                self.list_numbers.push(start);
+                self.push_container(Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: px(4),
+                    ..default()
+                });
+            }
+            Tag::Item => {
+                let marker = match self.list_numbers.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}.", *number - 1)
+                    }
+                    _ => "•".to_string(),
+                };
+                let item = self.push_block(Node {
+                    column_gap: px(8),
+                    ..default()
+                });
+                let marker = self
+                    .commands
+                    .spawn(self.text_bundle(marker, NOTE_TEXT_COLOR))
+                    .id();
+                let content = self
+                    .commands
+                    .spawn(Node {
+                        flex_direction: FlexDirection::Column,
+                        flex_grow: 1.,
+                        row_gap: px(4),
+                        ..default()
+                    })
+                    .id();
+                self.commands.entity(item).add_children(&[marker, content]);
+                self.containers.push(content);
+                self.text = None;
+            }
+            Tag::Table(alignments) => {
+                let columns = alignments.len().max(1) as u16;
+                self.table = Some((alignments, 0));
+                self.push_container((
+                    Node {
+                        display: Display::Grid,
+                        grid_template_columns: vec![RepeatedGridTrack::auto(columns)],
+                        border: UiRect::all(px(1)),
+                        ..default()
+                    },
+                    BorderColor::all(RULE_COLOR),
+                ));
+            }
+            Tag::TableHead => self.is_table_head = true,
+            Tag::TableRow => {
+                if let Some((_, column)) = &mut self.table {
+                    *column = 0;
+                }
+            }
+            Tag::TableCell => {
+                let justify_content = match &mut self.table {
+                    Some((alignments, column)) => {
+                        let alignment = alignments.get(*column).copied();
+                        *column += 1;
+                        match alignment {
+                            Some(Alignment::Center) => JustifyContent::Center,
+                            Some(Alignment::Right) => JustifyContent::End,
+                            _ => JustifyContent::Start,
+                        }
+                    }
+                    None => JustifyContent::Start,
+                };
+                self.push_container((
+                    Node {
+                        justify_content,
+                        padding: UiRect::axes(px(8), px(4)),
+                        border: UiRect::all(px(1)),
+                        ..default()
+                    },
+                    BorderColor::all(RULE_COLOR),
+                    BackgroundColor(if self.is_table_head {
+                        BUBBLE_BACKGROUND_COLOR
+                    } else {
+                        Color::NONE
+                    }),
+                ));
+            }
+            Tag::Emphasis => self.emphasis += 1,
+            Tag::Strong => self.strong += 1,
+            Tag::Strikethrough => self.strikethrough += 1,
+            Tag::Link { .. } => self.link += 1,
+            Tag::HtmlBlock
+            | Tag::FootnoteDefinition(_)
+            | Tag::DefinitionList
+            | Tag::DefinitionListTitle
+            | Tag::DefinitionListDefinition
+            | Tag::Superscript
+            | Tag::Subscript
+            | Tag::Image { .. }
+            | Tag::MetadataBlock(_) => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::HtmlBlock => self.text = None,
+            TagEnd::Heading(_) => {
+                self.heading = None;
+                self.text = None;
+            }
+            TagEnd::CodeBlock => {
+                let (language, code) = self.code_block.take().unwrap_or_default();
+                self.push_block(self.highlighter.code_block(&language, &code));
+            }
+            TagEnd::List(_) => {
+                self.list_numbers.pop();
+                self.pop_container();
+            }
+            TagEnd::Table => {
+                self.table = None;
+                self.pop_container();
+            }
+            TagEnd::TableHead => self.is_table_head = false,
+            TagEnd::BlockQuote(_) | TagEnd::Item | TagEnd::TableCell => self.pop_container(),
+            TagEnd::Emphasis => self.emphasis -= 1,
+            TagEnd::Strong => self.strong -= 1,
+            TagEnd::Strikethrough => self.strikethrough -= 1,
+            TagEnd::Link => self.link -= 1,
+            TagEnd::TableRow
+            | TagEnd::FootnoteDefinition
+            | TagEnd::DefinitionList
+            | TagEnd::DefinitionListTitle
+            | TagEnd::DefinitionListDefinition
+            | TagEnd::Superscript
+            | TagEnd::Subscript
+            | TagEnd::Image
+            | TagEnd::MetadataBlock(_) => {}
+        }
+    }
+
+    fn push_block(&mut self, bundle: impl Bundle) -> Entity {
+        let block = self.commands.spawn(bundle).id();
+        let container = *self.containers.last().unwrap();
+        self.commands.entity(container).add_child(block);
+        self.text = None;
+        block
+    }
+
+    fn push_container(&mut self, bundle: impl Bundle) {
+        let container = self.push_block(bundle);
+        self.containers.push(container);
+    }
+
+    fn pop_container(&mut self) {
+        self.containers.pop();
+        self.text = None;
+    }
+
+    fn font_size(&self) -> f32 {
+        match self.heading {
+            Some(HeadingLevel::H1) => 24.,
+            Some(HeadingLevel::H2) => 21.,
+            Some(HeadingLevel::H3) => 18.,
+            _ => FONT_SIZE,
+        }
+    }
+
+    fn text_bundle(&self, text: String, color: Color) -> impl Bundle + use<> {
+        let font_size = self.font_size();
+        (
+            Text::new(text),
+            TextFont::from_font_size(font_size),
+            TextColor(color),
+            LineHeight::Px(LINE_HEIGHT * font_size / FONT_SIZE),
+        )
+    }
+
+    /// The text node for inline content, creating it if needed.
+    fn text(&mut self) -> Entity {
+        match self.text {
+            Some(text) => text,
+            None => {
+                let text =
+                    self.push_block((self.text_bundle(String::new(), TEXT_COLOR), SelectableText));
+                self.text = Some(text);
+                text
+            }
+        }
+    }
+
+    fn push_span(&mut self, span: impl Bundle) {
+        let text = self.text();
+        let span = self.commands.spawn(span).id();
+        if self.strikethrough > 0 {
+            self.commands.entity(span).insert(Strikethrough);
+        }
+        if self.link > 0 {
+            self.commands.entity(span).insert(Underline);
+        }
+        self.commands.entity(text).add_child(span);
+    }
+
+    fn push_text(&mut self, text: &str) {
+        let is_bold = self.strong > 0 || self.heading.is_some() || self.is_table_head;
+        let is_italic = self.emphasis > 0;
+        let face = self.fonts.face(text, is_bold, is_italic);
+        let color = if self.link > 0 {
+            LINK_COLOR
+        } else if face.is_some() {
+            TEXT_COLOR
+        } else if is_italic {
+            EMPHASIS_COLOR
+        } else if is_bold {
+            STRONG_COLOR
+        } else {
+            TEXT_COLOR
+        };
+        let font = TextFont {
+            font: face.cloned().unwrap_or_default(),
+            font_size: self.font_size(),
+            ..default()
+        };
+        self.push_span((TextSpan::new(text), font, TextColor(color)));
+    }
+
+    fn push_code(&mut self, code: &str) {
+        self.push_span((
+            TextSpan::new(code),
+            TextFont::from_font_size(self.font_size() - 1.),
+            TextColor(CODE_TEXT_COLOR),
+            TextBackgroundColor(BUBBLE_BACKGROUND_COLOR),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(source: &str) -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin {
+                watch_for_changes_override: Some(false),
+                ..default()
+            },
+        ))
+        .init_asset::<Font>()
+        .init_resource::<CodeHighlighter>()
+        .init_resource::<MarkdownFonts>()
+        .add_systems(Update, update_markdown);
+        let root = app.world_mut().spawn(markdown(source.to_string())).id();
+        app.update();
+        (app, root)
+    }
+
+    fn children(world: &World, entity: Entity) -> Vec<Entity> {
+        world
+            .get::<Children>(entity)
+            .map(|children| children.to_vec())
+            .unwrap_or_default()
+    }
+
+    /// The content of every text node under `entity`, depth first.
+    fn texts(world: &World, entity: Entity) -> Vec<String> {
+        let mut texts = Vec::new();
+        if let Some(text) = world.get::<Text>(entity) {
+            let spans = children(world, entity)
+                .into_iter()
+                .filter_map(|span| world.get::<TextSpan>(span));
+            texts.push(spans.fold(text.0.clone(), |text, span| text + &span.0));
+        }
+        for child in children(world, entity) {
+            texts.extend(self::texts(world, child));
+        }
+        texts
+    }
+
+    /// The spans of the first text node under `entity`.
+    fn spans(world: &World, entity: Entity) -> Vec<Entity> {
+        if world.get::<Text>(entity).is_some() {
+            return children(world, entity);
+        }
+        children(world, entity)
+            .into_iter()
+            .map(|child| spans(world, child))
+            .find(|spans| !spans.is_empty())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn splits_the_source_into_top_level_blocks() {
+        let source = "# Title\n\nSome *text*\n\n- a\n- b\n";
+        let blocks: Vec<_> = top_level_blocks(source)
+            .into_iter()
+            .map(|(range, _)| &source[range])
+            .collect();
+        assert_eq!(blocks, ["# Title\n", "Some *text*\n", "- a\n- b\n"]);
+    }
+
+    #[test]
+    fn renders_only_the_blocks_that_changed() {
+        let (mut app, root) = app("First\n\nSecond");
+        let before = children(app.world(), root);
+        assert_eq!(texts(app.world(), root), ["First", "Second"]);
+
+        let mut markdown = app.world_mut().get_mut::<Markdown>(root).unwrap();
+        markdown.push_str(" part\n\nThird");
+        app.update();
+
+        let after = children(app.world(), root);
+        assert_eq!(after.len(), 3);
+        assert_eq!(after[0], before[0]);
+        assert_ne!(after[1], before[1]);
+        assert!(app.world().get_entity(before[1]).is_err());
+        assert_eq!(texts(app.world(), root), ["First", "Second part", "Third"]);
+    }
+
+    #[test]
+    fn styles_inline_text() {
+        let (app, root) = app("**bold** *italic* ***both*** **粗体** [link](https://a.b) `code`");
+        let world = app.world();
+        let fonts = world.resource::<MarkdownFonts>();
+        let styles: Vec<_> = spans(world, root)
+            .into_iter()
+            .map(|span| {
+                (
+                    world.get::<TextSpan>(span).unwrap().0.as_str(),
+                    world.get::<TextFont>(span).unwrap().font.id(),
+                    world.get::<TextColor>(span).unwrap().0,
+                    world.get::<Underline>(span).is_some(),
+                )
+            })
+            .filter(|(text, ..)| *text != " ")
+            .collect();
+        let regular = AssetId::default();
+        assert_eq!(
+            styles,
+            [
+                ("bold", fonts.bold.id(), TEXT_COLOR, false),
+                ("italic", fonts.italic.id(), TEXT_COLOR, false),
+                ("both", fonts.bold_italic.id(), TEXT_COLOR, false),
+                ("粗体", regular, STRONG_COLOR, false),
+                ("link", regular, LINK_COLOR, true),
+                ("code", regular, CODE_TEXT_COLOR, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers_and_nests_list_items() {
+        let (app, root) = app("3. first\n4. second\n   - inner\n\n- [x] done");
+        assert_eq!(
+            texts(app.world(), root),
+            ["3.", "first", "4.", "second", "•", "inner", "•", "☑ done"]
+        );
+    }
+
+    #[test]
+    fn hands_code_blocks_to_the_highlighter() {
+        let (mut app, root) = app("Before\n\n```rust title\nfn main() {}\n```\n");
+        let texts = texts(app.world(), root);
+        assert_eq!(texts[0], "Before");
+        assert_eq!(texts[1], "rust");
+        assert!(texts.contains(&"fn main() {}".to_string()));
+
+        // The code is highlighted in more than one color.
+        let code = app
+            .world_mut()
+            .query_filtered::<Entity, With<Text>>()
+            .iter(app.world())
+            .find(|&text| self::texts(app.world(), text) == ["fn main() {}"])
+            .unwrap();
+        let colors: Vec<_> = children(app.world(), code)
+            .into_iter()
+            .map(|span| app.world().get::<TextColor>(span).unwrap().0)
+            .collect();
+        assert!(colors.iter().any(|color| *color != colors[0]));
+    }
+}
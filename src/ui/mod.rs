@@ -1,18 +1,50 @@
+mod code;
+mod context_menu;
+mod export;
+mod find;
+mod import;
+mod markdown;
+mod notice;
+mod repair;
+mod search;
+mod settings;
+mod sidebar;
+
 use bevy::{
     ecs::{query::QuerySingleError, relationship::RelatedSpawner},
     feathers::{
         controls::{ButtonProps, ButtonVariant, button},
         theme::ThemedText,
     },
+    input_focus::AutoFocus,
     picking::hover::Hovered,
     prelude::*,
     text::LineHeight,
+    time::common_conditions::on_timer,
     ui_widgets::{Activate, ControlOrientation, CoreScrollbarThumb, Scrollbar, observe},
 };
 
 use crate::{
-    ai::{self, ReceiveMessage, SendMessage},
+    ai::{
+        self, AnswerAdded, EditMessage, MessageSent, ReceiveMessage, Regenerate, SelectBranch,
+        SendMessage, StopGeneration,
+    },
+    ui_input::{TextInput, TextInputCancel, TextInputSubmit, text_input, text_input_with},
     ui_scroll::GRAY1,
+    ui_select::SelectableText,
+};
+
+use code::CodeHighlighter;
+use context_menu::{StoredMessage, open_context_menu};
+use find::{Find, find_bar, open_find_bar, update_find_bar, update_find_matches};
+use import::{import_dropped_files, update_conversations_imported};
+use markdown::{Markdown, MarkdownFonts, markdown, update_markdown};
+use notice::update_notices;
+use repair::open_repair_screen;
+use search::{RevealMessage, reveal_message, update_found_highlight, update_search_results};
+use sidebar::{
+    MODIFIED_LABEL_INTERVAL, sidebar, update_conversation_list, update_modified_labels,
+    update_sidebar,
 };
 
 #[derive(Component)]
@@ -25,46 +57,108 @@ enum MessageRole {
     Assistant,
 }
 
-impl From<deepseek_api::Role> for MessageRole {
-    fn from(value: deepseek_api::Role) -> Self {
-        use deepseek_api::Role::*;
+impl From<ai::Role> for MessageRole {
+    fn from(value: ai::Role) -> Self {
+        use ai::Role::*;
         match value {
             System => MessageRole::System,
             User => MessageRole::User,
             Assistant => MessageRole::Assistant,
-            Tool => unreachable!(),
         }
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MessageStatus {
+    Done,
+    Streaming,
+    /// The user stopped the answer.
+    Interrupted,
+    /// Waiting for the current answer to finish before being sent.
+    Pending,
+}
+
+/// Where a message sits in the branch tree of the dialog.
+#[derive(Clone)]
+struct MessageBranch {
+    index: usize,
+    role: ai::Role,
+    /// The indices of every version of the message, oldest first.
+    versions: Vec<usize>,
+    /// Whether it is the last message of the shown branch.
+    is_latest: bool,
+}
+
+impl MessageBranch {
+    fn new(dialog: &ai::Dialog, index: usize) -> Self {
+        Self {
+            index,
+            role: dialog.message(index).role,
+            versions: dialog.siblings(index),
+            is_latest: dialog.branch().last() == Some(&index),
+        }
+    }
+}
+
+/// Only shown on the latest answer.
+#[derive(Component)]
+struct RegenerateButton;
+
 #[derive(Component)]
 struct StreamingMessage;
 
+#[derive(Component)]
+struct PendingNote;
+
+/// The box of a message the user sent that isn't in the dialog yet.
+#[derive(Component)]
+struct UnsentMessage;
+
+#[derive(Component)]
+struct ChatInput;
+
 #[derive(Component)]
 struct SendButton;
 
+#[derive(Component)]
+struct StopButton;
+
 const DEFAULT_FONT_PATH: &str = "assets/fonts/NotoSansSC-Regular.ttf";
 
-const TEXT_COLOR: Color = Color::Srgba(Srgba::rgb(0.9764706, 0.98039216, 0.9843137));
-const BUBBLE_BACKGROUND_COLOR: Color = Color::Srgba(Srgba::rgb(0.17254902, 0.17254902, 0.18039216));
+pub(crate) const TEXT_COLOR: Color = Color::Srgba(Srgba::rgb(0.9764706, 0.98039216, 0.9843137));
+pub(crate) const NOTE_TEXT_COLOR: Color = Color::Srgba(Srgba::rgb(0.5529412, 0.5529412, 0.5764706));
+pub(crate) const BUBBLE_BACKGROUND_COLOR: Color =
+    Color::Srgba(Srgba::rgb(0.17254902, 0.17254902, 0.18039216));
 const BACKGROUND_COLOR: Color = Color::Srgba(Srgba::rgb(0.08235294, 0.08235294, 0.09019608));
+const ERROR_TEXT_COLOR: Color = Color::Srgba(Srgba::rgb(1.0, 0.5372549, 0.5019608));
+const ERROR_BACKGROUND_COLOR: Color = Color::Srgba(Srgba::rgb(0.2509804, 0.1137255, 0.1176471));
 
-fn setup_ui(mut commands: Commands, messages: Res<ai::Dialog>) {
+fn setup_ui(mut commands: Commands, dialog: Res<ai::Dialog>) {
     commands.spawn(Camera2d);
-    commands.spawn(ui(messages.0.clone()));
+    commands.spawn((
+        Node {
+            width: percent(100),
+            height: percent(100),
+            ..default()
+        },
+        BackgroundColor(Srgba::hex("#151517").unwrap().into()),
+        children![sidebar(), chat(&dialog)],
+    ));
 }
 
-fn ui(messages: Vec<deepseek_api::message::Message>) -> impl Bundle {
+fn chat(dialog: &ai::Dialog) -> impl Bundle + use<> {
+    let message_boxes = stored_message_boxes(dialog);
+
     (
         Node {
             display: Display::Grid,
-            width: percent(100),
+            flex_grow: 1.,
+            min_width: px(0),
             height: percent(100),
             grid_template_columns: vec![RepeatedGridTrack::flex(1, 1.), RepeatedGridTrack::auto(1)],
             grid_template_rows: vec![RepeatedGridTrack::flex(1, 1.), RepeatedGridTrack::auto(1)],
             ..default()
         },
-        BackgroundColor(Srgba::hex("#151517").unwrap().into()),
         Children::spawn(SpawnWith(|parent: &mut RelatedSpawner<ChildOf>| {
             let scroll_area_id = parent
                 .spawn((
@@ -76,24 +170,7 @@ fn ui(messages: Vec<deepseek_api::message::Message>) -> impl Bundle {
                         padding: UiRect::all(px(8)),
                         ..default()
                     },
-                    Children::spawn(SpawnIter(
-                        messages
-                            .into_iter()
-                            .filter(|message| message.role() != deepseek_api::Role::System)
-                            .map(|message| {
-                                use deepseek_api::message::Message;
-                                message_box(
-                                    message.role().into(),
-                                    match message {
-                                        Message::System(system) => system.content,
-                                        Message::User(user) => user.content,
-                                        Message::Assistant(assistant) => assistant.content,
-                                        Message::Tool(tool) => tool.content,
-                                    },
-                                    false,
-                                )
-                            }),
-                    )),
+                    Children::spawn(SpawnIter(message_boxes.into_iter())),
                 ))
                 .id();
 
@@ -126,117 +203,490 @@ fn ui(messages: Vec<deepseek_api::message::Message>) -> impl Bundle {
                     grid_row: GridPlacement::start(2),
                     grid_column: GridPlacement::start_span(1, 2),
                     padding: UiRect::all(px(8)),
-                    justify_content: JustifyContent::Center,
+                    column_gap: px(8),
+                    align_items: AlignItems::End,
                     ..default()
                 },
-                Children::spawn(Spawn((
-                    Node {
-                        width: px(200),
-                        ..default()
-                    },
-                    children![(
-                        SendButton,
-                        button(
-                            ButtonProps {
-                                variant: ButtonVariant::Primary,
-                                ..default()
-                            },
-                            (),
-                            Spawn((Text::new("Start"), ThemedText))
-                        ),
+                children![
+                    (
+                        ChatInput,
+                        text_input("输入消息，Shift+Enter 换行"),
+                        AutoFocus,
                         observe(
-                            |_event: On<Activate>, mut send_message: MessageWriter<SendMessage>| {
-                                send_message.write(SendMessage::new("给我讲一个故事。"));
+                            |submit: On<TextInputSubmit>,
+                             mut text_inputs: Query<&mut TextInput>,
+                             mut send_message: MessageWriter<SendMessage>| {
+                                if let Ok(mut text_input) = text_inputs.get_mut(submit.entity) {
+                                    send_input_text(&mut text_input, &mut send_message);
+                                }
                             }
                         )
-                    )],
-                ))),
+                    ),
+                    (
+                        Node {
+                            width: px(80),
+                            flex_shrink: 0.,
+                            ..default()
+                        },
+                        children![
+                            (
+                                SendButton,
+                                button(
+                                    ButtonProps {
+                                        variant: ButtonVariant::Primary,
+                                        ..default()
+                                    },
+                                    (),
+                                    Spawn((Text::new("Send"), ThemedText))
+                                ),
+                                observe(
+                                    |_event: On<Activate>,
+                                     mut chat_input: Query<&mut TextInput, With<ChatInput>>,
+                                     mut send_message: MessageWriter<SendMessage>| {
+                                        if let Ok(mut text_input) = chat_input.single_mut() {
+                                            send_input_text(&mut text_input, &mut send_message);
+                                        }
+                                    }
+                                )
+                            ),
+                            (
+                                StopButton,
+                                button(
+                                    ButtonProps::default(),
+                                    (),
+                                    Spawn((Text::new("Stop"), ThemedText))
+                                ),
+                                observe(
+                                    |_event: On<Activate>,
+                                     mut stop_generation: MessageWriter<StopGeneration>| {
+                                        stop_generation.write(StopGeneration);
+                                    }
+                                )
+                            ),
+                        ],
+                    ),
+                ],
             ));
+
+            parent.spawn(find_bar());
         })),
     )
 }
 
-fn message_box(role: MessageRole, content: String, is_streaming: bool) -> impl Bundle + use<> {
+/// Sends the typed text, if any, and clears the input. Messages sent while an
+/// answer streams are queued.
+fn send_input_text(text_input: &mut TextInput, send_message: &mut MessageWriter<SendMessage>) {
+    let text = text_input.text().trim();
+    if text.is_empty() {
+        return;
+    }
+    send_message.write(SendMessage::new(text));
+    text_input.set_text("");
+}
+
+/// Boxes for the shown branch of a stored dialog, leaving out the system
+/// prompt.
+fn stored_message_boxes(dialog: &ai::Dialog) -> Vec<impl Bundle + use<>> {
+    dialog
+        .branch()
+        .into_iter()
+        .filter_map(|index| {
+            let message = dialog.message(index);
+            if message.role == ai::Role::System {
+                return None;
+            }
+            Some((
+                message_box(
+                    message.role.into(),
+                    message.content.clone(),
+                    if message.interrupted {
+                        MessageStatus::Interrupted
+                    } else {
+                        MessageStatus::Done
+                    },
+                    Some(MessageBranch::new(dialog, index)),
+                ),
+                StoredMessage(index),
+            ))
+        })
+        .collect()
+}
+
+/// A message bubble. Messages stored in the dialog get a row of actions below
+/// the bubble.
+fn message_box(
+    role: MessageRole,
+    content: String,
+    status: MessageStatus,
+    branch: Option<MessageBranch>,
+) -> impl Bundle + use<> {
     use MessageRole::*;
 
     (
         Node {
-            justify_content: match role {
+            flex_direction: FlexDirection::Column,
+            align_items: match role {
                 System => unreachable!(),
-                User => JustifyContent::End,
-                Assistant => JustifyContent::Start,
+                User => AlignItems::End,
+                Assistant => AlignItems::Start,
             },
+            row_gap: px(4),
             ..default()
         },
         role,
-        children![(
-            Node {
-                padding: UiRect::axes(px(16), px(10)),
-                align_items: AlignItems::Center,
-                border_radius: BorderRadius::all(px(22)),
+        observe(open_context_menu),
+        Children::spawn(SpawnWith(move |parent: &mut ChildSpawner| {
+            let message_box = parent.target_entity();
+            parent.spawn(message_bubble(role, content, status));
+            if let Some(branch) = branch {
+                parent.spawn(message_actions(message_box, branch));
+            }
+        })),
+    )
+}
+
+fn message_bubble(role: MessageRole, content: String, status: MessageStatus) -> impl Bundle {
+    use MessageRole::*;
+
+    (
+        Node {
+            flex_direction: FlexDirection::Column,
+            min_width: px(0),
+            max_width: percent(100),
+            padding: UiRect::axes(px(16), px(10)),
+            border_radius: BorderRadius::all(px(22)),
+            ..default()
+        },
+        BackgroundColor(match role {
+            System => unreachable!(),
+            User => BUBBLE_BACKGROUND_COLOR,
+            Assistant => BACKGROUND_COLOR,
+        }),
+        Children::spawn(SpawnWith(move |parent: &mut ChildSpawner| {
+            let text_font = TextFont {
+                font_size: 16.0,
                 ..default()
-            },
-            BackgroundColor(match role {
+            };
+
+            let mut message = match role {
                 System => unreachable!(),
-                User => BUBBLE_BACKGROUND_COLOR,
-                Assistant => BACKGROUND_COLOR,
-            }),
-            Children::spawn(SpawnWith(move |parent: &mut ChildSpawner| {
-                let text_color = TextColor(match role {
-                    System => unreachable!(),
-                    User => TEXT_COLOR,
-                    Assistant => TEXT_COLOR,
-                });
-
-                let text_font = TextFont {
-                    font_size: 16.0,
-                    ..default()
-                };
+                User => parent.spawn((
+                    Text::new(content),
+                    SelectableText,
+                    TextColor(TEXT_COLOR),
+                    text_font,
+                    LineHeight::Px(24.0),
+                )),
+                Assistant => parent.spawn(markdown(content)),
+            };
+            if status == MessageStatus::Streaming {
+                message.insert(StreamingMessage);
+            }
 
-                if is_streaming {
-                    parent.spawn((
-                        Text::new(content),
-                        text_color,
-                        text_font,
-                        LineHeight::Px(24.0),
-                        StreamingMessage,
-                    ));
-                } else {
+            match status {
+                MessageStatus::Interrupted => {
+                    parent.spawn(note("Interrupted"));
+                }
+                MessageStatus::Pending => {
+                    parent.spawn((note("Pending"), PendingNote));
+                }
+                MessageStatus::Done | MessageStatus::Streaming => {}
+            }
+        })),
+    )
+}
+
+/// The arrows switching between the versions of a message, if it has several,
+/// with the edit button of a user message or the regenerate button of the
+/// latest answer.
+fn message_actions(message_box: Entity, branch: MessageBranch) -> impl Bundle {
+    let MessageBranch {
+        index,
+        role,
+        versions,
+        is_latest,
+    } = branch;
+    let position = versions
+        .iter()
+        .position(|&version| version == index)
+        .unwrap_or(0);
+    let previous = position.checked_sub(1).map(|position| versions[position]);
+    let next = versions.get(position + 1).copied();
+    let version_count = versions.len();
+
+    (
+        Node {
+            align_items: AlignItems::Center,
+            column_gap: px(4),
+            ..default()
+        },
+        Children::spawn(SpawnWith(move |parent: &mut ChildSpawner| {
+            if version_count > 1 {
+                parent.spawn(select_branch_button("<", previous));
+                parent.spawn(note(&format!("{}/{}", position + 1, version_count)));
+                parent.spawn(select_branch_button(">", next));
+            }
+            match role {
+                ai::Role::User => {
+                    parent.spawn(edit_button(message_box, index));
+                }
+                ai::Role::Assistant if is_latest => {
                     parent.spawn((
-                        Text::new(content),
-                        text_color,
-                        text_font,
-                        LineHeight::Px(24.0),
+                        RegenerateButton,
+                        button(
+                            ButtonProps::default(),
+                            (),
+                            Spawn((Text::new("Regenerate"), ThemedText)),
+                        ),
+                        observe(
+                            move |_event: On<Activate>, mut regenerate: MessageWriter<Regenerate>| {
+                                regenerate.write(Regenerate(index));
+                            },
+                        ),
                     ));
                 }
-            })),
+                ai::Role::System | ai::Role::Assistant => {}
+            }
+        })),
+    )
+}
+
+/// Replaces the bubble of a user message with an input to edit it.
+fn edit_button(message_box: Entity, index: usize) -> impl Bundle {
+    (
+        button(
+            ButtonProps::default(),
+            (),
+            Spawn((Text::new("Edit"), ThemedText)),
+        ),
+        observe(
+            move |_event: On<Activate>,
+                  dialog: Res<ai::Dialog>,
+                  children: Query<&Children>,
+                  mut nodes: Query<&mut Node>,
+                  mut commands: Commands| {
+                let Some(&bubble) = children
+                    .get(message_box)
+                    .ok()
+                    .and_then(|children| children.first())
+                else {
+                    return;
+                };
+                let Ok(mut bubble_node) = nodes.get_mut(bubble) else {
+                    return;
+                };
+                if bubble_node.display == Display::None {
+                    return;
+                }
+                bubble_node.display = Display::None;
+
+                let content = &dialog.message(index).content;
+                let submit = observe(
+                    move |submit: On<TextInputSubmit>,
+                          text_inputs: Query<&TextInput>,
+                          mut edit_message: MessageWriter<EditMessage>| {
+                        if let Ok(text_input) = text_inputs.get(submit.entity) {
+                            let content = text_input.text().trim();
+                            if !content.is_empty() {
+                                edit_message.write(EditMessage {
+                                    index,
+                                    content: content.to_string(),
+                                });
+                            }
+                        }
+                    },
+                );
+                let input = commands
+                    .spawn((text_input_with("", content), AutoFocus, submit))
+                    .id();
+                let editor = commands
+                    .spawn(Node {
+                        width: percent(100),
+                        ..default()
+                    })
+                    .add_child(input)
+                    .id();
+                // Escape puts the bubble back.
+                commands.entity(input).observe(
+                    move |_cancel: On<TextInputCancel>,
+                          mut nodes: Query<&mut Node>,
+                          mut commands: Commands| {
+                        if let Ok(mut bubble_node) = nodes.get_mut(bubble) {
+                            bubble_node.display = Display::Flex;
+                        }
+                        commands.entity(editor).despawn();
+                    },
+                );
+                commands.entity(message_box).insert_children(0, &[editor]);
+            },
+        ),
+    )
+}
+
+/// An arrow showing another version of a message, doing nothing at the ends.
+fn select_branch_button(label: &str, target: Option<usize>) -> impl Bundle + use<> {
+    (
+        button(
+            ButtonProps::default(),
+            (),
+            Spawn((Text::new(label), ThemedText)),
+        ),
+        observe(
+            move |_event: On<Activate>, mut select_branch: MessageWriter<SelectBranch>| {
+                if let Some(target) = target {
+                    select_branch.write(SelectBranch(target));
+                }
+            },
+        ),
+    )
+}
+
+/// A small line of secondary text, e.g. a status under a message.
+pub(super) fn note(text: &str) -> impl Bundle + use<> {
+    (
+        Text::new(text),
+        TextColor(NOTE_TEXT_COLOR),
+        TextFont {
+            font_size: 12.0,
+            ..default()
+        },
+        LineHeight::Px(18.0),
+    )
+}
+
+fn error_box(error: &ai::ChatError) -> impl Bundle + use<> {
+    (
+        Node {
+            justify_content: JustifyContent::Start,
+            ..default()
+        },
+        children![(
+            Node {
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::axes(px(16), px(10)),
+                border_radius: BorderRadius::all(px(22)),
+                ..default()
+            },
+            BackgroundColor(ERROR_BACKGROUND_COLOR),
+            children![
+                (
+                    Text::new(error.title()),
+                    SelectableText,
+                    TextColor(ERROR_TEXT_COLOR),
+                    TextFont {
+                        font_size: 16.0,
+                        ..default()
+                    },
+                    LineHeight::Px(24.0),
+                ),
+                (
+                    Text::new(error.detail()),
+                    SelectableText,
+                    TextColor(TEXT_COLOR),
+                    TextFont {
+                        font_size: 14.0,
+                        ..default()
+                    },
+                    LineHeight::Px(20.0),
+                ),
+            ],
         )],
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 fn update_send_message(
     mut send_message: MessageReader<SendMessage>,
     mut dialog: Query<Entity, With<Dialog>>,
+    ai_dialog: Res<ai::Dialog>,
+    stream_receiver: Option<Res<ai::StreamReceiver>>,
+    pending_messages: Res<ai::PendingMessages>,
+    unsent_messages: Query<(), With<UnsentMessage>>,
+    regenerate_buttons: Query<Entity, With<RegenerateButton>>,
     mut commands: Commands,
 ) {
     let dialog = dialog.single_mut().unwrap();
 
+    // A message waits if an answer is streaming or messages sent before it
+    // haven't gone out yet.
+    let mut is_busy = stream_receiver.is_some()
+        || pending_messages.of(&ai_dialog.id).next().is_some()
+        || !unsent_messages.is_empty();
     for send_message in send_message.read() {
+        // The answer is no longer the latest one.
+        for button in &regenerate_buttons {
+            commands.entity(button).despawn();
+        }
+
         let message_box = commands
-            .spawn(message_box(
-                MessageRole::User,
-                send_message.0.clone(),
-                false,
+            .spawn((
+                message_box(
+                    MessageRole::User,
+                    send_message.0.clone(),
+                    if is_busy {
+                        MessageStatus::Pending
+                    } else {
+                        MessageStatus::Done
+                    },
+                    None,
+                ),
+                UnsentMessage,
             ))
             .id();
         commands.entity(dialog).add_child(message_box);
+        is_busy = true;
+    }
+}
+
+/// Gives the oldest unsent message its actions once it is sent, removing its
+/// pending note.
+fn update_message_sent(
+    mut message_sent: MessageReader<MessageSent>,
+    dialog: Res<ai::Dialog>,
+    dialog_node: Query<&Children, With<Dialog>>,
+    children: Query<&Children>,
+    unsent_messages: Query<(), With<UnsentMessage>>,
+    pending_notes: Query<(), With<PendingNote>>,
+    mut commands: Commands,
+) {
+    let mut sent = message_sent
+        .read()
+        .map(|MessageSent(index)| *index)
+        .peekable();
+    if sent.peek().is_none() {
+        return;
+    }
+
+    // Messages are sent in the order they were queued, which is also the
+    // order of their boxes in the dialog.
+    for message_box in dialog_node.single().unwrap() {
+        if sent.peek().is_none() {
+            break;
+        }
+        if unsent_messages.contains(*message_box) {
+            let index = sent.next().unwrap();
+            if let Some(note) = children
+                .iter_descendants(*message_box)
+                .find(|entity| pending_notes.contains(*entity))
+            {
+                commands.entity(note).despawn();
+            }
+            commands
+                .entity(*message_box)
+                .remove::<UnsentMessage>()
+                .insert(StoredMessage(index))
+                .with_child(message_actions(
+                    *message_box,
+                    MessageBranch::new(&dialog, index),
+                ));
+        }
     }
 }
 
 fn update_receive_message(
     mut receive_message: MessageReader<ReceiveMessage>,
     mut dialog: Query<Entity, With<Dialog>>,
-    mut text_query: Query<(Entity, &mut Text), With<StreamingMessage>>,
+    mut text_query: Query<(Entity, &mut Markdown, &ChildOf), With<StreamingMessage>>,
     mut commands: Commands,
 ) {
     let dialog = dialog.single_mut().unwrap();
@@ -245,12 +695,22 @@ fn update_receive_message(
         return;
     }
 
+    let mut errors = Vec::new();
     match text_query.single_mut() {
-        Ok((entity, mut text)) => {
+        Ok((entity, mut text, child_of)) => {
             for receive_message in receive_message {
                 match receive_message {
                     ReceiveMessage::Content(content) => {
-                        text.0 += content;
+                        text.push_str(content);
+                    }
+                    ReceiveMessage::Error(error) => {
+                        errors.push(error.clone());
+                    }
+                    ReceiveMessage::Interrupted => {
+                        commands.entity(entity).remove::<StreamingMessage>();
+                        commands
+                            .entity(child_of.parent())
+                            .with_child(note("Interrupted"));
                     }
                     ReceiveMessage::Finished => {
                         commands.entity(entity).remove::<StreamingMessage>();
@@ -261,23 +721,168 @@ fn update_receive_message(
         Err(QuerySingleError::NoEntities(_)) => {
             let mut text = String::new();
             let mut is_finished = false;
+            let mut is_interrupted = false;
             for receive_message in receive_message {
                 match receive_message {
                     ReceiveMessage::Content(content) => {
                         text += content;
                     }
+                    ReceiveMessage::Error(error) => {
+                        errors.push(error.clone());
+                    }
+                    ReceiveMessage::Interrupted => {
+                        is_finished = true;
+                        is_interrupted = true;
+                    }
                     ReceiveMessage::Finished => {
                         is_finished = true;
                     }
                 }
             }
-            let message_box = commands
-                .spawn(message_box(MessageRole::Assistant, text, !is_finished))
-                .id();
-            commands.entity(dialog).add_child(message_box);
+            if !text.is_empty() || !is_finished {
+                let message_box = commands
+                    .spawn(message_box(
+                        MessageRole::Assistant,
+                        text,
+                        if is_interrupted {
+                            MessageStatus::Interrupted
+                        } else if is_finished {
+                            MessageStatus::Done
+                        } else {
+                            MessageStatus::Streaming
+                        },
+                        None,
+                    ))
+                    .id();
+                commands.entity(dialog).add_child(message_box);
+            }
+        }
+        Err(err) => {
+            log::error!("Failed to show the received answer: {err}");
+            return;
         }
-        _ => unreachable!(),
     };
+
+    for error in errors {
+        let error_box = commands.spawn(error_box(&error)).id();
+        commands.entity(dialog).add_child(error_box);
+    }
+}
+
+/// Replaces the shown messages when another conversation or branch is
+/// opened.
+///
+/// The answer still being streamed and the messages waiting for it stay
+/// below the new branch, as they continue it. Another conversation shows its
+/// own waiting messages.
+#[allow(clippy::too_many_arguments)]
+fn update_dialog_switched(
+    mut conversation_switched: MessageReader<ai::ConversationSwitched>,
+    mut branch_switched: MessageReader<ai::BranchSwitched>,
+    dialog: Res<ai::Dialog>,
+    pending_messages: Res<ai::PendingMessages>,
+    stream_receiver: Option<Res<ai::StreamReceiver>>,
+    dialog_node: Single<(Entity, Option<&Children>), With<Dialog>>,
+    children: Query<&Children>,
+    streaming_messages: Query<(), With<StreamingMessage>>,
+    unsent_messages: Query<(), With<UnsentMessage>>,
+    mut commands: Commands,
+) {
+    let conversation_switched = conversation_switched.read().count() > 0;
+    let branch_switched = branch_switched.read().count() > 0;
+    if !conversation_switched && !branch_switched {
+        return;
+    }
+
+    // A stream started this frame replaced the one shown.
+    let is_streaming = stream_receiver.is_some_and(|stream_receiver| !stream_receiver.is_added());
+    let (dialog_node, message_boxes) = *dialog_node;
+    for message_box in message_boxes.into_iter().flatten() {
+        let is_kept = !conversation_switched
+            && (unsent_messages.contains(*message_box)
+                || is_streaming
+                    && children
+                        .iter_descendants(*message_box)
+                        .any(|entity| streaming_messages.contains(entity)));
+        if !is_kept {
+            commands.entity(*message_box).despawn();
+        }
+    }
+    let stored: Vec<_> = stored_message_boxes(&dialog)
+        .into_iter()
+        .map(|message_box| commands.spawn(message_box).id())
+        .collect();
+    commands.entity(dialog_node).insert_children(0, &stored);
+
+    if conversation_switched {
+        for message in pending_messages.of(&dialog.id) {
+            commands.entity(dialog_node).with_child((
+                message_box(
+                    MessageRole::User,
+                    message.to_string(),
+                    MessageStatus::Pending,
+                    None,
+                ),
+                UnsentMessage,
+            ));
+        }
+    }
+}
+
+/// Adds the actions of an answer once it is kept in the dialog.
+fn update_answer_added(
+    mut answer_added: MessageReader<AnswerAdded>,
+    ai_dialog: Res<ai::Dialog>,
+    dialog: Query<&Children, With<Dialog>>,
+    roles: Query<&MessageRole>,
+    mut commands: Commands,
+) {
+    let Some(AnswerAdded(index)) = answer_added.read().last() else {
+        return;
+    };
+
+    // Messages queued meanwhile come after the answer.
+    if let Some(message_box) = dialog
+        .single()
+        .unwrap()
+        .iter()
+        .rev()
+        .find(|entity| matches!(roles.get(*entity), Ok(MessageRole::Assistant)))
+    {
+        commands
+            .entity(message_box)
+            .insert(StoredMessage(*index))
+            .with_child(message_actions(
+                message_box,
+                MessageBranch::new(&ai_dialog, *index),
+            ));
+    }
+}
+
+/// Shows the stop button instead of the send button while an answer streams.
+fn update_send_button(
+    stream_receiver: Option<Res<ai::StreamReceiver>>,
+    mut send_button: Query<&mut Node, (With<SendButton>, Without<StopButton>)>,
+    mut stop_button: Query<&mut Node, (With<StopButton>, Without<SendButton>)>,
+) {
+    let is_streaming = stream_receiver.is_some();
+    let display = |visible: bool| {
+        if visible {
+            Display::Flex
+        } else {
+            Display::None
+        }
+    };
+
+    for (mut node, visible) in send_button
+        .iter_mut()
+        .map(|node| (node, !is_streaming))
+        .chain(stop_button.iter_mut().map(|node| (node, is_streaming)))
+    {
+        if node.display != display(visible) {
+            node.display = display(visible);
+        }
+    }
 }
 
 pub(crate) struct UiPlugin;
@@ -289,7 +894,36 @@ impl Plugin for UiPlugin {
         let mut assets = app.world_mut().resource_mut::<Assets<_>>();
         assets.insert(AssetId::default(), asset).unwrap();
 
-        app.add_systems(Startup, setup_ui)
-            .add_systems(Update, (update_send_message, update_receive_message));
+        app.init_resource::<CodeHighlighter>()
+            .init_resource::<MarkdownFonts>()
+            .init_resource::<RevealMessage>()
+            .init_resource::<Find>()
+            .add_systems(Startup, (setup_ui, open_repair_screen.after(setup_ui)))
+            .add_systems(
+                Update,
+                (
+                    (update_send_message, update_message_sent).chain(),
+                    update_receive_message,
+                    update_send_button,
+                    update_answer_added.after(update_receive_message),
+                    update_markdown.after(update_receive_message),
+                    update_dialog_switched
+                        .after(ai::DialogSystems)
+                        .before(update_receive_message),
+                    update_sidebar,
+                    update_conversation_list.after(ai::DialogSystems),
+                    update_modified_labels.run_if(on_timer(MODIFIED_LABEL_INTERVAL)),
+                    update_found_highlight,
+                    update_search_results,
+                    update_notices,
+                    import_dropped_files.before(ai::DialogSystems),
+                    update_conversations_imported.after(ai::DialogSystems),
+                    (open_find_bar, update_find_bar).chain(),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                (reveal_message, update_find_matches).after(bevy::ui::UiSystems::PostLayout),
+            );
     }
 }
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, text::LineHeight};
+
+use super::{BUBBLE_BACKGROUND_COLOR, TEXT_COLOR};
+
+/// How long a notice stays on screen.
+const NOTICE_TIME: Duration = Duration::from_secs(4);
+
+/// A short message shown at the bottom of the window, e.g. where a file was
+/// written.
+#[derive(Component)]
+pub(super) struct Notice(Timer);
+
+/// Shows `text` as a notice, replacing the one shown before.
+pub(super) fn show_notice(
+    text: impl Into<String>,
+    notices: &Query<Entity, With<Notice>>,
+    commands: &mut Commands,
+) {
+    for entity in notices {
+        commands.entity(entity).despawn();
+    }
+    commands.spawn((
+        Notice(Timer::new(NOTICE_TIME, TimerMode::Once)),
+        Node {
+            position_type: PositionType::Absolute,
+            width: percent(100),
+            bottom: px(96),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        GlobalZIndex(1),
+        Pickable::IGNORE,
+        children![(
+            Node {
+                max_width: px(600),
+                padding: UiRect::axes(px(16), px(8)),
+                border_radius: BorderRadius::all(px(8)),
+                ..default()
+            },
+            BackgroundColor(BUBBLE_BACKGROUND_COLOR),
+            Pickable::IGNORE,
+            children![(
+                Text::new(text),
+                TextColor(TEXT_COLOR),
+                TextFont::from_font_size(14.),
+                LineHeight::Px(20.),
+                Pickable::IGNORE,
+            )],
+        )],
+    ));
+}
+
+/// Removes notices once their time is up.
+pub(super) fn update_notices(
+    time: Res<Time>,
+    mut notices: Query<(Entity, &mut Notice)>,
+    mut commands: Commands,
+) {
+    for (entity, mut notice) in &mut notices {
+        if notice.0.tick(time.delta()).is_finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use bevy::{
+    feathers::{
+        controls::{ButtonProps, ButtonVariant, button},
+        theme::ThemedText,
+    },
+    prelude::*,
+    text::LineHeight,
+    ui_widgets::{Activate, observe},
+};
+
+use super::{
+    BACKGROUND_COLOR, BUBBLE_BACKGROUND_COLOR, ERROR_TEXT_COLOR, TEXT_COLOR, note,
+    settings::{OVERLAY_COLOR, PANEL_WIDTH, open_settings},
+};
+use crate::{
+    ai::{self, LoadFailure, LoadReport},
+    ui_select::SelectableText,
+};
+
+/// Shown at startup after a default config was written, or when files
+/// couldn't be loaded, explaining what happened to them.
+#[derive(Component)]
+pub(super) struct RepairScreen;
+
+pub(super) fn open_repair_screen(report: Res<LoadReport>, mut commands: Commands) {
+    if report.first_run || !report.failures.is_empty() {
+        commands.spawn(repair_screen(&report));
+    }
+}
+
+fn repair_screen(report: &LoadReport) -> impl Bundle + use<> {
+    let title = if report.failures.is_empty() {
+        "Welcome"
+    } else {
+        "Some files couldn't be loaded"
+    };
+    let mut intro = Vec::new();
+    if report.first_run {
+        intro.push(format!(
+            "A default {} was written next to the app. Choose a provider and enter an API key \
+             in the settings to start chatting.",
+            ai::CONFIG_PATH
+        ));
+    }
+    if !report.failures.is_empty() {
+        intro.push(
+            "The app started with fresh files in their place. Fix the backups and move them \
+             back to restore them."
+                .to_string(),
+        );
+    }
+    let failures: Vec<_> = report.failures.iter().map(failure).collect();
+
+    (
+        RepairScreen,
+        Node {
+            position_type: PositionType::Absolute,
+            width: percent(100),
+            height: percent(100),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BackgroundColor(OVERLAY_COLOR),
+        GlobalZIndex(1),
+        children![(
+            Node {
+                flex_direction: FlexDirection::Column,
+                width: px(PANEL_WIDTH),
+                max_width: percent(100),
+                max_height: percent(100),
+                padding: UiRect::all(px(16)),
+                row_gap: px(12),
+                overflow: Overflow::scroll_y(),
+                border_radius: BorderRadius::all(px(16)),
+                ..default()
+            },
+            BackgroundColor(BACKGROUND_COLOR),
+            Children::spawn((
+                Spawn((
+                    Text::new(title),
+                    TextColor(TEXT_COLOR),
+                    TextFont::from_font_size(20.),
+                    LineHeight::Px(28.),
+                )),
+                Spawn((
+                    Text::new(intro.join("\n\n")),
+                    TextColor(TEXT_COLOR),
+                    TextFont::from_font_size(14.),
+                    LineHeight::Px(20.),
+                )),
+                SpawnIter(failures.into_iter()),
+                Spawn((
+                    Node {
+                        justify_content: JustifyContent::End,
+                        column_gap: px(8),
+                        ..default()
+                    },
+                    children![
+                        (
+                            button(
+                                ButtonProps::default(),
+                                (),
+                                Spawn((Text::new("Continue"), ThemedText))
+                            ),
+                            observe(close_repair_screen)
+                        ),
+                        (
+                            button(
+                                ButtonProps {
+                                    variant: ButtonVariant::Primary,
+                                    ..default()
+                                },
+                                (),
+                                Spawn((Text::new("Open settings"), ThemedText))
+                            ),
+                            observe(close_repair_screen),
+                            observe(open_settings)
+                        ),
+                    ],
+                )),
+            )),
+        )],
+    )
+}
+
+/// The file that failed, why, and where it went.
+fn failure(failure: &LoadFailure) -> impl Bundle + use<> {
+    let outcome = match &failure.backup {
+        Some(backup) => format!("Moved to {} and replaced.", backup.display()),
+        None => "It couldn't be moved aside and was left as it is.".to_string(),
+    };
+
+    (
+        Node {
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::axes(px(12), px(8)),
+            row_gap: px(4),
+            border_radius: BorderRadius::all(px(8)),
+            ..default()
+        },
+        BackgroundColor(BUBBLE_BACKGROUND_COLOR),
+        children![
+            (
+                Text::new(failure.path.display().to_string()),
+                SelectableText,
+                TextColor(TEXT_COLOR),
+                TextFont::from_font_size(14.),
+                LineHeight::Px(20.),
+            ),
+            (
+                Text::new(failure.error.to_string()),
+                SelectableText,
+                TextColor(ERROR_TEXT_COLOR),
+                TextFont::from_font_size(14.),
+                LineHeight::Px(20.),
+            ),
+            note(&outcome),
+        ],
+    )
+}
+
+fn close_repair_screen(
+    _activate: On<Activate>,
+    screen: Single<Entity, With<RepairScreen>>,
+    mut commands: Commands,
+) {
+    commands.entity(*screen).despawn();
+}
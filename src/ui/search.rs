@@ -0,0 +1,280 @@
+use std::time::Duration;
+
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task, futures::check_ready},
+    text::LineHeight,
+    ui_widgets::observe,
+};
+
+use super::{
+    Dialog, NOTE_TEXT_COLOR, TEXT_COLOR, context_menu::StoredMessage, note,
+    sidebar::ConversationList,
+};
+use crate::{
+    ai::{self, SearchResult, ShowMessage},
+    ui_input::{TextInput, TextInputCancel, TextInputSubmit, text_input},
+};
+
+const MATCH_BACKGROUND_COLOR: Color = Color::Srgba(Srgba::rgb(0.4, 0.32941177, 0.10980392));
+const FOUND_OUTLINE_COLOR: Color = Color::Srgba(Srgba::rgb(0.9490196, 0.7490196, 0.2627451));
+/// How long the opened message stays outlined.
+const FOUND_HIGHLIGHT_TIME: Duration = Duration::from_secs(2);
+/// Space left above the opened message when scrolling to it.
+const REVEAL_MARGIN: f32 = 16.;
+
+/// Shown instead of the conversation list while there is a search.
+#[derive(Component)]
+pub(super) struct SearchResults;
+
+/// Reads the stored conversations in the background for a search.
+#[derive(Component)]
+pub(super) struct SearchTask(Task<Vec<SearchResult>>);
+
+/// A message to scroll to once its conversation is shown.
+#[derive(Resource, Default)]
+pub(super) struct RevealMessage(Option<(String, usize)>);
+
+/// Outlines the bubble of an opened search result for a moment.
+#[derive(Component)]
+pub(super) struct FoundHighlight(Timer);
+
+/// Searches all conversations on Enter, showing the results once
+/// [`update_search_results`] has them. Escape ends the search.
+pub(super) fn search_input() -> impl Bundle {
+    (
+        Node::default(),
+        children![(
+            text_input("Search"),
+            observe(
+                |submit: On<TextInputSubmit>,
+                 text_inputs: Query<&TextInput>,
+                 results: Single<(Entity, &mut Node), With<SearchResults>>,
+                 mut list: Single<&mut Node, (With<ConversationList>, Without<SearchResults>)>,
+                 mut commands: Commands| {
+                    let Ok(text_input) = text_inputs.get(submit.entity) else {
+                        return;
+                    };
+                    let query = text_input.text().trim().to_string();
+                    let (results_entity, mut results_node) = results.into_inner();
+                    commands
+                        .entity(results_entity)
+                        .despawn_children()
+                        .remove::<SearchTask>();
+                    if query.is_empty() {
+                        results_node.display = Display::None;
+                        list.display = Display::Flex;
+                        return;
+                    }
+
+                    // Replacing the task of an earlier search cancels it.
+                    let task = AsyncComputeTaskPool::get().spawn(async move { ai::search(&query) });
+                    commands
+                        .entity(results_entity)
+                        .insert(SearchTask(task))
+                        .with_child(results_note("Searching…"));
+                    results_node.display = Display::Flex;
+                    list.display = Display::None;
+                }
+            ),
+            observe(
+                |cancel: On<TextInputCancel>,
+                 mut text_inputs: Query<&mut TextInput>,
+                 results: Single<(Entity, &mut Node), With<SearchResults>>,
+                 mut list: Single<&mut Node, (With<ConversationList>, Without<SearchResults>)>,
+                 mut commands: Commands| {
+                    if let Ok(mut text_input) = text_inputs.get_mut(cancel.entity) {
+                        text_input.set_text("");
+                    }
+                    let (results_entity, mut results_node) = results.into_inner();
+                    commands
+                        .entity(results_entity)
+                        .despawn_children()
+                        .remove::<SearchTask>();
+                    results_node.display = Display::None;
+                    list.display = Display::Flex;
+                }
+            ),
+        )],
+    )
+}
+
+pub(super) fn search_results() -> impl Bundle {
+    (
+        SearchResults,
+        Node {
+            display: Display::None,
+            flex_direction: FlexDirection::Column,
+            flex_grow: 1.,
+            row_gap: px(2),
+            overflow: Overflow::scroll_y(),
+            ..default()
+        },
+    )
+}
+
+/// Shows the results of a finished search.
+pub(super) fn update_search_results(
+    mut searches: Query<(Entity, &mut SearchTask)>,
+    mut commands: Commands,
+) {
+    for (entity, mut task) in &mut searches {
+        let Some(results) = check_ready(&mut task.0) else {
+            continue;
+        };
+        let mut results_entity = commands.entity(entity);
+        results_entity.despawn_children().remove::<SearchTask>();
+        if results.is_empty() {
+            results_entity.with_child(results_note("No results"));
+        }
+        for result in results {
+            results_entity.with_child(result_row(result));
+        }
+    }
+}
+
+/// A note in place of the results.
+fn results_note(text: &str) -> impl Bundle + use<> {
+    (
+        note(text),
+        Node {
+            padding: UiRect::axes(px(10), px(6)),
+            ..default()
+        },
+    )
+}
+
+/// The conversation and the matching part of a message. Clicking it opens the
+/// message.
+fn result_row(result: SearchResult) -> impl Bundle {
+    let SearchResult {
+        conversation,
+        title,
+        index,
+        role,
+        snippet: [before, matched, after],
+    } = result;
+    let role = match role {
+        ai::Role::User => "You",
+        ai::Role::System | ai::Role::Assistant => "Assistant",
+    };
+
+    (
+        Node {
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::axes(px(10), px(6)),
+            row_gap: px(2),
+            border_radius: BorderRadius::all(px(8)),
+            ..default()
+        },
+        observe(
+            move |click: On<Pointer<Click>>,
+                  mut show_message: MessageWriter<ShowMessage>,
+                  mut reveal_message: ResMut<RevealMessage>| {
+                if click.button == PointerButton::Primary {
+                    show_message.write(ShowMessage {
+                        conversation: conversation.clone(),
+                        index,
+                    });
+                    reveal_message.0 = Some((conversation.clone(), index));
+                }
+            },
+        ),
+        children![
+            (
+                Text::new(title),
+                TextColor(TEXT_COLOR),
+                TextFont::from_font_size(14.),
+                LineHeight::Px(20.),
+                TextLayout::new_with_no_wrap(),
+                Node {
+                    overflow: Overflow::clip(),
+                    ..default()
+                },
+                Pickable::IGNORE,
+                children![(
+                    TextSpan::new(format!(" · {role}")),
+                    TextColor(NOTE_TEXT_COLOR),
+                    TextFont::from_font_size(12.),
+                )],
+            ),
+            (
+                Text::new(before),
+                TextColor(NOTE_TEXT_COLOR),
+                TextFont::from_font_size(12.),
+                LineHeight::Px(18.),
+                Pickable::IGNORE,
+                children![
+                    (
+                        TextSpan::new(matched),
+                        TextColor(TEXT_COLOR),
+                        TextBackgroundColor(MATCH_BACKGROUND_COLOR),
+                        TextFont::from_font_size(12.),
+                    ),
+                    (
+                        TextSpan::new(after),
+                        TextColor(NOTE_TEXT_COLOR),
+                        TextFont::from_font_size(12.),
+                    ),
+                ],
+            ),
+        ],
+    )
+}
+
+/// Scrolls the dialog to the opened message once it is laid out, and
+/// outlines its bubble.
+pub(super) fn reveal_message(
+    mut reveal_message: ResMut<RevealMessage>,
+    ai_dialog: Res<ai::Dialog>,
+    dialog: Single<(&ComputedNode, &UiGlobalTransform, &mut ScrollPosition), With<Dialog>>,
+    message_boxes: Query<(&StoredMessage, &ComputedNode, &UiGlobalTransform, &Children)>,
+    mut commands: Commands,
+) {
+    let Some((conversation, index)) = &reveal_message.0 else {
+        return;
+    };
+    // The conversation is opened in the frame the result was clicked, so
+    // another one is still shown if it couldn't be loaded.
+    if *conversation != ai_dialog.id {
+        reveal_message.0 = None;
+        return;
+    }
+    let Some((_, node, transform, children)) = message_boxes
+        .iter()
+        .find(|(stored_message, ..)| stored_message.0 == *index)
+    else {
+        return;
+    };
+    if node.size() == Vec2::ZERO {
+        return;
+    }
+
+    let (dialog_node, dialog_transform, mut scroll_position) = dialog.into_inner();
+    let top = (transform.translation.y - node.size().y / 2.)
+        - (dialog_transform.translation.y - dialog_node.size().y / 2.);
+    scroll_position.y =
+        (scroll_position.y + top * dialog_node.inverse_scale_factor() - REVEAL_MARGIN).max(0.);
+
+    if let Some(&bubble) = children.first() {
+        commands.entity(bubble).insert((
+            Outline::new(px(2), px(0), FOUND_OUTLINE_COLOR),
+            FoundHighlight(Timer::new(FOUND_HIGHLIGHT_TIME, TimerMode::Once)),
+        ));
+    }
+    reveal_message.0 = None;
+}
+
+pub(super) fn update_found_highlight(
+    mut highlights: Query<(Entity, &mut FoundHighlight)>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for (entity, mut highlight) in &mut highlights {
+        if highlight.0.tick(time.delta()).is_finished() {
+            commands
+                .entity(entity)
+                .remove::<(Outline, FoundHighlight)>();
+        }
+    }
+}
@@ -0,0 +1,617 @@
+use bevy::{
+    feathers::{
+        controls::{ButtonProps, ButtonVariant, SliderProps, button, radio, slider, toggle_switch},
+        theme::ThemedText,
+    },
+    input_focus::AutoFocus,
+    prelude::*,
+    text::LineHeight,
+    ui::{Checked, InteractionDisabled},
+    ui_widgets::{
+        Activate, RadioGroup, SliderPrecision, SliderStep, SliderValue, ValueChange,
+        checkbox_self_update, observe, slider_self_update,
+    },
+};
+
+use reqwest::Url;
+
+use super::{BACKGROUND_COLOR, ERROR_TEXT_COLOR, TEXT_COLOR, note};
+use crate::{
+    ai::{Config, Provider},
+    ui_input::{
+        Masked, Placeholder, SingleLine, TextInput, TextInputCancel, TextInputSubmit,
+        text_input_with,
+    },
+};
+
+pub(super) const OVERLAY_COLOR: Color = Color::Srgba(Srgba::new(0., 0., 0., 0.5));
+pub(super) const PANEL_WIDTH: f32 = 440.;
+
+/// Covers the window while the settings are open.
+#[derive(Component)]
+pub(super) struct SettingsPanel;
+
+/// A text field of the settings.
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SettingsField {
+    ApiKey,
+    BaseUrl,
+    Model,
+    MaxTokens,
+}
+
+#[derive(Component, Clone, Copy)]
+pub(super) struct ProviderChoice(Provider);
+
+/// A sampling parameter that is either left to the provider or set with a
+/// slider.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum SamplingParam {
+    Temperature,
+    TopP,
+}
+
+impl SamplingParam {
+    fn label(self) -> &'static str {
+        match self {
+            SamplingParam::Temperature => "Temperature",
+            SamplingParam::TopP => "Top P",
+        }
+    }
+
+    /// The highest value providers accept. The lowest is 0.
+    fn max(self) -> f32 {
+        match self {
+            SamplingParam::Temperature => 2.,
+            SamplingParam::TopP => 1.,
+        }
+    }
+}
+
+/// Whether the parameter is set, rather than left to the provider.
+#[derive(Component)]
+pub(super) struct SamplingToggle(SamplingParam);
+
+#[derive(Component)]
+pub(super) struct SamplingSlider(SamplingParam);
+
+#[derive(Component)]
+pub(super) struct SettingsError;
+
+/// Opens the settings, filled in from the current [`Config`].
+pub(super) fn open_settings(
+    _activate: On<Activate>,
+    config: Res<Config>,
+    panels: Query<(), With<SettingsPanel>>,
+    mut commands: Commands,
+) {
+    if panels.is_empty() {
+        commands.spawn(settings_panel(&config));
+    }
+}
+
+fn settings_panel(config: &Config) -> impl Bundle {
+    let provider = config.provider;
+    let api_key = config.api_key.clone();
+    let base_url = config.base_url.clone();
+    let model = config.model.clone();
+    let max_tokens = config
+        .max_tokens
+        .map(|max_tokens| max_tokens.to_string())
+        .unwrap_or_default();
+    let temperature = config.temperature;
+    let top_p = config.top_p;
+
+    (
+        SettingsPanel,
+        Node {
+            position_type: PositionType::Absolute,
+            width: percent(100),
+            height: percent(100),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BackgroundColor(OVERLAY_COLOR),
+        GlobalZIndex(1),
+        children![(
+            Node {
+                flex_direction: FlexDirection::Column,
+                width: px(PANEL_WIDTH),
+                max_width: percent(100),
+                max_height: percent(100),
+                padding: UiRect::all(px(16)),
+                row_gap: px(12),
+                overflow: Overflow::scroll_y(),
+                border_radius: BorderRadius::all(px(16)),
+                ..default()
+            },
+            BackgroundColor(BACKGROUND_COLOR),
+            Children::spawn(SpawnWith(move |parent: &mut ChildSpawner| {
+                parent.spawn((
+                    Text::new("Settings"),
+                    TextColor(TEXT_COLOR),
+                    TextFont::from_font_size(20.),
+                    LineHeight::Px(28.),
+                ));
+
+                parent.spawn(setting("Provider")).with_children(|parent| {
+                    parent
+                        .spawn((
+                            Node {
+                                flex_wrap: FlexWrap::Wrap,
+                                column_gap: px(16),
+                                row_gap: px(8),
+                                ..default()
+                            },
+                            RadioGroup,
+                            observe(select_provider),
+                        ))
+                        .with_children(|parent| {
+                            for choice in Provider::ALL {
+                                let mut radio = parent.spawn(radio(
+                                    ProviderChoice(choice),
+                                    Spawn((Text::new(choice.label()), ThemedText)),
+                                ));
+                                if choice == provider {
+                                    radio.insert(Checked);
+                                }
+                            }
+                        });
+                });
+
+                parent.spawn(setting("API key")).with_children(|parent| {
+                    parent.spawn(row()).with_children(|parent| {
+                        parent
+                            .spawn(settings_input(SettingsField::ApiKey, "API key", &api_key))
+                            .insert((Masked, AutoFocus));
+                        parent.spawn((
+                            toggle_switch(()),
+                            observe(checkbox_self_update),
+                            observe(show_api_key),
+                        ));
+                        parent.spawn(note("Show"));
+                    });
+                });
+
+                parent.spawn(setting("Base URL")).with_children(|parent| {
+                    parent.spawn(settings_input(
+                        SettingsField::BaseUrl,
+                        provider.default_base_url(),
+                        &base_url,
+                    ));
+                });
+
+                parent.spawn(setting("Model")).with_children(|parent| {
+                    parent.spawn(settings_input(
+                        SettingsField::Model,
+                        model_placeholder(provider),
+                        &model,
+                    ));
+                });
+
+                for (param, value) in [
+                    (SamplingParam::Temperature, temperature),
+                    (SamplingParam::TopP, top_p),
+                ] {
+                    parent
+                        .spawn(setting(param.label()))
+                        .with_children(|parent| {
+                            sampling_row(parent, param, value, 1.);
+                        });
+                }
+
+                parent.spawn(setting("Max tokens")).with_children(|parent| {
+                    parent.spawn(settings_input(
+                        SettingsField::MaxTokens,
+                        "Provider default",
+                        &max_tokens,
+                    ));
+                });
+
+                parent.spawn((
+                    SettingsError,
+                    Text::default(),
+                    TextColor(ERROR_TEXT_COLOR),
+                    TextFont::from_font_size(14.),
+                    LineHeight::Px(20.),
+                ));
+
+                parent.spawn((
+                    Node {
+                        justify_content: JustifyContent::End,
+                        column_gap: px(8),
+                        ..default()
+                    },
+                    children![
+                        (
+                            button(
+                                ButtonProps::default(),
+                                (),
+                                Spawn((Text::new("Cancel"), ThemedText))
+                            ),
+                            observe(close_settings)
+                        ),
+                        (
+                            button(
+                                ButtonProps {
+                                    variant: ButtonVariant::Primary,
+                                    ..default()
+                                },
+                                (),
+                                Spawn((Text::new("Save"), ThemedText))
+                            ),
+                            observe(save_settings)
+                        ),
+                    ],
+                ));
+            })),
+        )],
+    )
+}
+
+/// A column holding the label and controls of one setting.
+fn setting(label: &str) -> impl Bundle + use<> {
+    (
+        Node {
+            flex_direction: FlexDirection::Column,
+            row_gap: px(4),
+            ..default()
+        },
+        children![note(label)],
+    )
+}
+
+fn row() -> impl Bundle {
+    Node {
+        align_items: AlignItems::Center,
+        column_gap: px(8),
+        ..default()
+    }
+}
+
+/// A single-line input for `field`. Enter saves and Escape closes the
+/// settings.
+fn settings_input(field: SettingsField, placeholder: &str, text: &str) -> impl Bundle {
+    (
+        text_input_with(placeholder, text),
+        SingleLine,
+        field,
+        observe(|_submit: On<TextInputSubmit>, mut commands: Commands| {
+            commands.run_system_cached(save_settings_system);
+        }),
+        observe(
+            |_cancel: On<TextInputCancel>,
+             panel: Single<Entity, With<SettingsPanel>>,
+             mut commands: Commands| {
+                commands.entity(*panel).despawn();
+            },
+        ),
+    )
+}
+
+/// A toggle choosing whether `param` is set, and a slider from 0 to `max`
+/// setting it. The slider starts at `unset_value` when the parameter isn't set.
+fn sampling_row(
+    parent: &mut ChildSpawner,
+    param: SamplingParam,
+    value: Option<f32>,
+    unset_value: f32,
+) {
+    parent.spawn(row()).with_children(|parent| {
+        let mut toggle = parent.spawn((
+            toggle_switch(SamplingToggle(param)),
+            observe(checkbox_self_update),
+            observe(toggle_sampling),
+        ));
+        if value.is_some() {
+            toggle.insert(Checked);
+        }
+        let mut slider = parent.spawn(slider(
+            SliderProps {
+                value: value.unwrap_or(unset_value),
+                min: 0.,
+                max: param.max(),
+            },
+            (
+                SamplingSlider(param),
+                SliderStep(0.05),
+                SliderPrecision(2),
+                observe(slider_self_update),
+            ),
+        ));
+        if value.is_none() {
+            slider.insert(InteractionDisabled);
+        }
+    });
+}
+
+/// The default model of `provider`, shown while the model field is empty.
+fn model_placeholder(provider: Provider) -> &'static str {
+    provider.default_model().unwrap_or("Required")
+}
+
+/// Checks the chosen provider and shows its defaults as placeholders.
+fn select_provider(
+    value_change: On<ValueChange<Entity>>,
+    radios: Query<(Entity, &ProviderChoice, Has<Checked>)>,
+    mut inputs: Query<(&SettingsField, &mut Placeholder, &mut TextInput)>,
+    mut commands: Commands,
+) {
+    for (entity, _, is_checked) in &radios {
+        if entity == value_change.value {
+            commands.entity(entity).insert(Checked);
+        } else if is_checked {
+            commands.entity(entity).remove::<Checked>();
+        }
+    }
+
+    let Ok((_, &ProviderChoice(provider), _)) = radios.get(value_change.value) else {
+        return;
+    };
+    for (field, mut placeholder, mut text_input) in &mut inputs {
+        placeholder.0 = match field {
+            SettingsField::BaseUrl => provider.default_base_url(),
+            SettingsField::Model => model_placeholder(provider),
+            _ => continue,
+        }
+        .to_string();
+        // Shows the new placeholder.
+        text_input.set_changed();
+    }
+}
+
+fn show_api_key(
+    value_change: On<ValueChange<bool>>,
+    mut inputs: Query<(Entity, &SettingsField, &mut TextInput)>,
+    mut commands: Commands,
+) {
+    for (entity, field, mut text_input) in &mut inputs {
+        if *field != SettingsField::ApiKey {
+            continue;
+        }
+        if value_change.value {
+            commands.entity(entity).remove::<Masked>();
+        } else {
+            commands.entity(entity).insert(Masked);
+        }
+        // Shows the text with or without the mask.
+        text_input.set_changed();
+    }
+}
+
+/// Enables the slider of a parameter while it is set.
+fn toggle_sampling(
+    value_change: On<ValueChange<bool>>,
+    toggles: Query<&SamplingToggle>,
+    sliders: Query<(Entity, &SamplingSlider)>,
+    mut commands: Commands,
+) {
+    let Ok(SamplingToggle(param)) = toggles.get(value_change.source) else {
+        return;
+    };
+    for (entity, SamplingSlider(slider_param)) in &sliders {
+        if slider_param != param {
+            continue;
+        }
+        if value_change.value {
+            commands.entity(entity).remove::<InteractionDisabled>();
+        } else {
+            commands.entity(entity).insert(InteractionDisabled);
+        }
+    }
+}
+
+fn close_settings(
+    _activate: On<Activate>,
+    panel: Single<Entity, With<SettingsPanel>>,
+    mut commands: Commands,
+) {
+    commands.entity(*panel).despawn();
+}
+
+fn save_settings(_activate: On<Activate>, mut commands: Commands) {
+    commands.run_system_cached(save_settings_system);
+}
+
+/// Validates the entered settings and writes them to the config file, or
+/// shows what is wrong.
+#[allow(clippy::too_many_arguments)]
+fn save_settings_system(
+    fields: Query<(&SettingsField, &TextInput)>,
+    providers: Query<(&ProviderChoice, Has<Checked>)>,
+    toggles: Query<(&SamplingToggle, Has<Checked>)>,
+    sliders: Query<(&SamplingSlider, &SliderValue)>,
+    error_text: Single<&mut Text, With<SettingsError>>,
+    panel: Single<Entity, With<SettingsPanel>>,
+    mut config: ResMut<Config>,
+    mut commands: Commands,
+) {
+    let text = |field: SettingsField| {
+        fields
+            .iter()
+            .find(|(other, _)| **other == field)
+            .map_or("", |(_, text_input)| text_input.text().trim())
+    };
+    let sampling = |param: SamplingParam| {
+        let is_set = toggles
+            .iter()
+            .any(|(SamplingToggle(other), is_checked)| *other == param && is_checked);
+        let value = sliders
+            .iter()
+            .find(|(SamplingSlider(other), _)| *other == param)
+            .map(|(_, value)| (value.0 * 100.).round() / 100.);
+        value.filter(|_| is_set)
+    };
+
+    let mut new_config = config.clone();
+    new_config.provider = providers
+        .iter()
+        .find(|(_, is_checked)| *is_checked)
+        .map_or(config.provider, |(&ProviderChoice(provider), _)| provider);
+    new_config.api_key = text(SettingsField::ApiKey).to_string();
+    new_config.base_url = text(SettingsField::BaseUrl).to_string();
+    new_config.model = text(SettingsField::Model).to_string();
+    new_config.temperature = sampling(SamplingParam::Temperature);
+    new_config.top_p = sampling(SamplingParam::TopP);
+
+    let result = parse_max_tokens(text(SettingsField::MaxTokens))
+        .and_then(|max_tokens| {
+            new_config.max_tokens = max_tokens;
+            validate(&new_config)
+        })
+        .and_then(|()| {
+            new_config
+                .save()
+                .map_err(|err| format!("Failed to save the settings: {err}"))
+        });
+    match result {
+        Ok(()) => {
+            *config = new_config;
+            commands.entity(*panel).despawn();
+        }
+        Err(err) => error_text.into_inner().0 = err,
+    }
+}
+
+fn parse_max_tokens(text: &str) -> Result<Option<u32>, String> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+    match text.parse() {
+        Ok(0) | Err(_) => Err("Max tokens must be a whole number above 0.".to_string()),
+        Ok(max_tokens) => Ok(Some(max_tokens)),
+    }
+}
+
+fn validate(config: &Config) -> Result<(), String> {
+    if config.provider.needs_api_key() && config.api_key.is_empty() {
+        return Err(format!("{} needs an API key.", config.provider.label()));
+    }
+    if !config.base_url.is_empty() {
+        let url = Url::parse(&config.base_url)
+            .map_err(|err| format!("The base URL isn't valid: {err}."))?;
+        if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+            return Err("The base URL must start with http:// or https://.".to_string());
+        }
+    }
+    for (param, value) in [
+        (SamplingParam::Temperature, config.temperature),
+        (SamplingParam::TopP, config.top_p),
+    ] {
+        if value.is_some_and(|value| !(0. ..=param.max()).contains(&value)) {
+            return Err(format!(
+                "{} must be between 0 and {}.",
+                param.label(),
+                param.max()
+            ));
+        }
+    }
+    if config.model.is_empty() && config.provider.default_model().is_none() {
+        return Err(format!(
+            "{} servers need a model name.",
+            config.provider.label()
+        ));
+    }
+    if config.model.contains(char::is_whitespace) {
+        return Err("The model name can't contain spaces.".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(provider: Provider) -> Config {
+        Config {
+            provider,
+            api_key: "key".to_string(),
+            ..default()
+        }
+    }
+
+    #[test]
+    fn parses_max_tokens() {
+        assert_eq!(parse_max_tokens(""), Ok(None));
+        assert_eq!(parse_max_tokens("1024"), Ok(Some(1024)));
+        for text in ["0", "-1", "1.5", "many", "99999999999"] {
+            assert!(parse_max_tokens(text).is_err(), "{text} was accepted");
+        }
+    }
+
+    #[test]
+    fn requires_an_api_key_where_the_provider_needs_one() {
+        for provider in Provider::ALL {
+            let mut config = config(provider);
+            config.model = "model".to_string();
+            config.api_key.clear();
+            assert_eq!(
+                validate(&config).is_err(),
+                provider.needs_api_key(),
+                "{}",
+                provider.label()
+            );
+        }
+        let mut config = config(Provider::DeepSeek);
+        config.api_key.clear();
+        assert_eq!(
+            validate(&config),
+            Err("DeepSeek needs an API key.".to_string())
+        );
+    }
+
+    #[test]
+    fn accepts_only_web_base_urls() {
+        let mut config = config(Provider::DeepSeek);
+        for base_url in ["", "https://api.example.com/v1", "http://localhost:8000"] {
+            config.base_url = base_url.to_string();
+            assert_eq!(validate(&config), Ok(()), "{base_url}");
+        }
+        for base_url in [
+            "api.example.com",
+            "localhost:8000",
+            "https://",
+            "ftp://example.com",
+            "http://exa mple.com",
+        ] {
+            config.base_url = base_url.to_string();
+            assert!(validate(&config).is_err(), "{base_url} was accepted");
+        }
+    }
+
+    #[test]
+    fn keeps_sampling_parameters_in_range() {
+        let mut config = config(Provider::DeepSeek);
+        config.temperature = Some(2.);
+        config.top_p = Some(0.);
+        assert_eq!(validate(&config), Ok(()));
+
+        config.temperature = Some(2.5);
+        assert_eq!(
+            validate(&config),
+            Err("Temperature must be between 0 and 2.".to_string())
+        );
+        config.temperature = Some(-0.1);
+        assert!(validate(&config).is_err());
+
+        config.temperature = None;
+        config.top_p = Some(1.1);
+        assert_eq!(
+            validate(&config),
+            Err("Top P must be between 0 and 1.".to_string())
+        );
+        config.top_p = Some(f32::NAN);
+        assert!(validate(&config).is_err());
+    }
+
+    #[test]
+    fn requires_a_model_without_spaces() {
+        let mut config = config(Provider::OpenAiCompatible);
+        assert!(validate(&config).is_err());
+        config.model = "my model".to_string();
+        assert!(validate(&config).is_err());
+        config.model = "my-model".to_string();
+        assert_eq!(validate(&config), Ok(()));
+    }
+}
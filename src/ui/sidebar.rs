@@ -0,0 +1,375 @@
+use std::time::{Duration, SystemTime};
+
+use bevy::{
+    feathers::{
+        controls::{ButtonProps, ButtonVariant, button},
+        theme::ThemedText,
+    },
+    input_focus::AutoFocus,
+    prelude::*,
+    text::LineHeight,
+    ui::UiGlobalTransform,
+    ui_widgets::{Activate, observe},
+};
+
+use super::{
+    BUBBLE_BACKGROUND_COLOR, NOTE_TEXT_COLOR, TEXT_COLOR,
+    context_menu::{menu_item, popup_menu},
+    export::{ExportFormat, export},
+    notice::{Notice, show_notice},
+    search::{search_input, search_results},
+    settings::open_settings,
+};
+use crate::{
+    ai::{
+        self, ConversationInfo, ConversationSwitched, Conversations, DeleteConversation,
+        NewConversation, RenameConversation, SwitchConversation,
+    },
+    ui_input::{TextInput, TextInputSubmit, text_input_with},
+};
+
+const SIDEBAR_WIDTH: f32 = 220.;
+/// How often the modification times in the list are brought up to date.
+pub(super) const MODIFIED_LABEL_INTERVAL: Duration = Duration::from_secs(10);
+const SIDEBAR_BACKGROUND_COLOR: Color =
+    Color::Srgba(Srgba::rgb(0.10588235, 0.10588235, 0.11372549));
+
+/// The column listing the stored conversations.
+#[derive(Component, Default)]
+pub(super) struct Sidebar {
+    collapsed: bool,
+}
+
+#[derive(Component)]
+pub(super) struct SidebarToggleLabel;
+
+/// Hidden while the sidebar is collapsed.
+#[derive(Component)]
+pub(super) struct SidebarContent;
+
+#[derive(Component)]
+pub(super) struct ConversationList;
+
+/// Shows how long ago a conversation was modified.
+#[derive(Component)]
+pub(super) struct ModifiedLabel(SystemTime);
+
+pub(super) fn sidebar() -> impl Bundle {
+    (
+        Sidebar::default(),
+        Node {
+            flex_direction: FlexDirection::Column,
+            width: px(SIDEBAR_WIDTH),
+            height: percent(100),
+            flex_shrink: 0.,
+            padding: UiRect::all(px(8)),
+            row_gap: px(8),
+            ..default()
+        },
+        BackgroundColor(SIDEBAR_BACKGROUND_COLOR),
+        children![
+            (
+                Node {
+                    column_gap: px(8),
+                    ..default()
+                },
+                children![
+                    (
+                        button(
+                            ButtonProps::default(),
+                            (),
+                            Spawn((Text::new("«"), SidebarToggleLabel, ThemedText))
+                        ),
+                        observe(|_event: On<Activate>, mut sidebar: Single<&mut Sidebar>| {
+                            sidebar.collapsed = !sidebar.collapsed;
+                        })
+                    ),
+                    (
+                        SidebarContent,
+                        Node {
+                            flex_grow: 1.,
+                            ..default()
+                        },
+                        children![(
+                            button(
+                                ButtonProps {
+                                    variant: ButtonVariant::Primary,
+                                    ..default()
+                                },
+                                (),
+                                Spawn((Text::new("New chat"), ThemedText))
+                            ),
+                            observe(
+                                |_event: On<Activate>,
+                                 mut new_conversation: MessageWriter<NewConversation>| {
+                                    new_conversation.write(NewConversation);
+                                }
+                            )
+                        )],
+                    ),
+                ],
+            ),
+            (
+                SidebarContent,
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    flex_grow: 1.,
+                    min_height: px(0),
+                    row_gap: px(8),
+                    ..default()
+                },
+                children![
+                    search_input(),
+                    search_results(),
+                    (
+                        ConversationList,
+                        Node {
+                            flex_direction: FlexDirection::Column,
+                            flex_grow: 1.,
+                            row_gap: px(2),
+                            overflow: Overflow::scroll_y(),
+                            ..default()
+                        },
+                    ),
+                    (
+                        button(
+                            ButtonProps::default(),
+                            (),
+                            Spawn((Text::new("Settings"), ThemedText))
+                        ),
+                        observe(open_settings)
+                    ),
+                ],
+            ),
+        ],
+    )
+}
+
+/// One entry of the list. The current conversation is highlighted and can be
+/// renamed or deleted.
+fn conversation_row(info: &ConversationInfo, is_current: bool, now: SystemTime) -> impl Bundle {
+    let id = info.id.clone();
+    let title = info.title.clone();
+    let modified = info.modified;
+    let modified_label = relative_time(modified, now);
+
+    (
+        Node {
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::axes(px(10), px(6)),
+            row_gap: px(2),
+            border_radius: BorderRadius::all(px(8)),
+            ..default()
+        },
+        BackgroundColor(if is_current {
+            BUBBLE_BACKGROUND_COLOR
+        } else {
+            Color::NONE
+        }),
+        observe({
+            let id = id.clone();
+            move |click: On<Pointer<Click>>,
+                  mut switch_conversation: MessageWriter<SwitchConversation>| {
+                if click.button == PointerButton::Primary {
+                    switch_conversation.write(SwitchConversation(id.clone()));
+                }
+            }
+        }),
+        Children::spawn(SpawnWith(move |parent: &mut ChildSpawner| {
+            let row = parent.target_entity();
+            let title_id = parent
+                .spawn((
+                    Text::new(title.clone()),
+                    TextColor(TEXT_COLOR),
+                    TextFont::from_font_size(14.),
+                    LineHeight::Px(20.),
+                    TextLayout::new_with_no_wrap(),
+                    Node {
+                        overflow: Overflow::clip(),
+                        ..default()
+                    },
+                    Pickable::IGNORE,
+                ))
+                .id();
+            parent.spawn((
+                Text::new(modified_label),
+                ModifiedLabel(modified),
+                TextColor(NOTE_TEXT_COLOR),
+                TextFont::from_font_size(12.),
+                LineHeight::Px(18.),
+                Pickable::IGNORE,
+            ));
+            if !is_current {
+                return;
+            }
+
+            let rename_id = id.clone();
+            parent.spawn((
+                Node {
+                    column_gap: px(4),
+                    ..default()
+                },
+                children![
+                    (
+                        button(
+                            ButtonProps::default(),
+                            (),
+                            Spawn((Text::new("Rename"), ThemedText))
+                        ),
+                        observe(move |_event: On<Activate>, mut commands: Commands| {
+                            commands.entity(title_id).despawn();
+                            let id = rename_id.clone();
+                            let submit = observe(
+                                move |submit: On<TextInputSubmit>,
+                                      text_inputs: Query<&TextInput>,
+                                      mut rename: MessageWriter<RenameConversation>| {
+                                    if let Ok(text_input) = text_inputs.get(submit.entity) {
+                                        rename.write(RenameConversation {
+                                            id: id.clone(),
+                                            name: text_input.text().to_string(),
+                                        });
+                                    }
+                                },
+                            );
+                            let input = commands
+                                .spawn((text_input_with("Name", &title), AutoFocus, submit))
+                                .id();
+                            commands.entity(row).insert_children(0, &[input]);
+                        })
+                    ),
+                    (
+                        button(
+                            ButtonProps::default(),
+                            (),
+                            Spawn((Text::new("Export"), ThemedText))
+                        ),
+                        observe(open_export_menu)
+                    ),
+                    (
+                        button(
+                            ButtonProps::default(),
+                            (),
+                            Spawn((Text::new("Delete"), ThemedText))
+                        ),
+                        observe(
+                            move |_event: On<Activate>,
+                                  mut delete_conversation: MessageWriter<DeleteConversation>| {
+                                delete_conversation.write(DeleteConversation(id.clone()));
+                            }
+                        )
+                    ),
+                ],
+            ));
+        })),
+    )
+}
+
+/// Opens the export formats below the Export button.
+fn open_export_menu(
+    activate: On<Activate>,
+    buttons: Query<(&ComputedNode, &UiGlobalTransform)>,
+    mut commands: Commands,
+) {
+    let Ok((node, transform)) = buttons.get(activate.entity) else {
+        return;
+    };
+    let bottom_left = transform.translation + node.size() * Vec2::new(-0.5, 0.5);
+    let items = ExportFormat::ALL.map(|format| {
+        (
+            menu_item(format.label()),
+            observe(
+                move |_event: On<Activate>,
+                      dialog: Res<ai::Dialog>,
+                      notices: Query<Entity, With<Notice>>,
+                      mut commands: Commands| {
+                    let text = match export(&dialog, format) {
+                        Ok(path) => format!("Exported to {}", path.display()),
+                        Err(err) => {
+                            log::error!("Failed to export the conversation: {err}");
+                            format!("Failed to export: {err}")
+                        }
+                    };
+                    show_notice(text, &notices, &mut commands);
+                },
+            ),
+        )
+    });
+    commands.spawn(popup_menu(
+        bottom_left * node.inverse_scale_factor(),
+        Children::spawn(SpawnIter(items.into_iter())),
+    ));
+}
+
+/// How long ago `time` was, e.g. "5 min ago".
+fn relative_time(time: SystemTime, now: SystemTime) -> String {
+    let seconds = now.duration_since(time).unwrap_or_default().as_secs();
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86400 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} d ago", seconds / 86400),
+    }
+}
+
+/// Keeps the modification times in the list relative to now.
+pub(super) fn update_modified_labels(mut labels: Query<(&ModifiedLabel, &mut Text)>) {
+    let now = SystemTime::now();
+    for (ModifiedLabel(modified), mut text) in &mut labels {
+        let label = relative_time(*modified, now);
+        if text.0 != label {
+            text.0 = label;
+        }
+    }
+}
+
+/// Collapses or expands the sidebar.
+pub(super) fn update_sidebar(
+    mut sidebar: Query<(&Sidebar, &mut Node), Changed<Sidebar>>,
+    mut content: Query<&mut Node, (With<SidebarContent>, Without<Sidebar>)>,
+    mut toggle_label: Query<&mut Text, With<SidebarToggleLabel>>,
+) {
+    let Ok((sidebar, mut node)) = sidebar.single_mut() else {
+        return;
+    };
+
+    node.width = if sidebar.collapsed {
+        Val::Auto
+    } else {
+        px(SIDEBAR_WIDTH)
+    };
+    for mut node in &mut content {
+        node.display = if sidebar.collapsed {
+            Display::None
+        } else {
+            Display::Flex
+        };
+    }
+    for mut text in &mut toggle_label {
+        text.0 = if sidebar.collapsed { "»" } else { "«" }.to_string();
+    }
+}
+
+/// Rebuilds the list when conversations are added, renamed, deleted or
+/// opened.
+pub(super) fn update_conversation_list(
+    conversations: Res<Conversations>,
+    dialog: Res<ai::Dialog>,
+    mut conversation_switched: MessageReader<ConversationSwitched>,
+    list: Single<Entity, With<ConversationList>>,
+    mut commands: Commands,
+) {
+    let is_switched = conversation_switched.read().count() > 0;
+    if !conversations.is_changed() && !is_switched {
+        return;
+    }
+
+    let now = SystemTime::now();
+    let rows: Vec<_> = conversations
+        .iter()
+        .map(|info| conversation_row(info, info.id == dialog.id, now))
+        .collect();
+    commands
+        .entity(*list)
+        .despawn_children()
+        .insert(Children::spawn(SpawnIter(rows.into_iter())));
+}
@@ -0,0 +1,1069 @@
+use std::ops::Range;
+
+use bevy::{
+    input::{
+        ButtonState,
+        keyboard::{Key, KeyboardInput},
+    },
+    input_focus::{FocusedInput, InputFocus, tab_navigation::TabIndex},
+    prelude::*,
+    text::{ComputedTextBlock, LineHeight, TextLayoutInfo, Underline},
+    ui::UiGlobalTransform,
+    window::{Ime, PrimaryWindow},
+};
+
+use crate::ui::{BUBBLE_BACKGROUND_COLOR, NOTE_TEXT_COLOR, TEXT_COLOR};
+
+const SELECTION_COLOR: Color = Color::Srgba(Srgba::new(0.2627451, 0.4745098, 0.8509804, 0.6));
+/// Shown for every character of a [`Masked`] input.
+const MASK_CHAR: char = '•';
+
+const FONT_SIZE: f32 = 16.;
+const LINE_HEIGHT: f32 = 24.;
+const CARET_WIDTH: f32 = 2.;
+
+/// An editable multi-line text field.
+///
+/// Enter triggers [`TextInputSubmit`], Escape triggers [`TextInputCancel`] and
+/// Shift+Enter inserts a line break.
+#[derive(Component, Default)]
+#[require(TabIndex)]
+pub(crate) struct TextInput {
+    text: String,
+    /// Byte offset of the cursor in `text`.
+    cursor: usize,
+    /// Byte offset of the other end of the selection, if any.
+    anchor: Option<usize>,
+    /// Text being composed with an input method, shown at the cursor until it
+    /// is committed.
+    preedit: String,
+}
+
+/// A [`TextInput`] without line breaks: Shift+Enter submits as well, and line
+/// breaks in pasted text become spaces.
+#[derive(Component)]
+pub(crate) struct SingleLine;
+
+/// A [`TextInput`] showing a dot for every character, e.g. for an API key.
+/// Its content can't be copied or cut.
+#[derive(Component)]
+pub(crate) struct Masked;
+
+/// Text shown in an empty [`TextInput`].
+#[derive(Component, Clone)]
+pub(crate) struct Placeholder(pub(crate) String);
+
+/// Triggered on a [`TextInput`] when the user presses Enter.
+#[derive(EntityEvent, Clone)]
+pub(crate) struct TextInputSubmit {
+    pub(crate) entity: Entity,
+    /// Whether Shift was held, which only submits a [`SingleLine`] input.
+    pub(crate) shift: bool,
+}
+
+/// Triggered on a [`TextInput`] when the user presses Escape.
+#[derive(EntityEvent, Clone)]
+pub(crate) struct TextInputCancel {
+    pub(crate) entity: Entity,
+}
+
+/// The entities a [`TextInput`] renders into.
+#[derive(Component)]
+struct TextInputParts {
+    /// The text before the selection, with the input method's preedit, the
+    /// selection and the text after it as child spans.
+    text: Entity,
+    preedit: Entity,
+    selection: Entity,
+    after: Entity,
+    caret: Entity,
+}
+
+impl TextInput {
+    /// A field holding `text`, with the cursor at its end.
+    pub(crate) fn new(text: &str) -> Self {
+        let mut text_input = Self::default();
+        text_input.set_text(text);
+        text_input
+    }
+
+    pub(crate) fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replaces the content, moving the cursor to its end.
+    pub(crate) fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+        self.anchor = None;
+    }
+
+    /// The selected byte range, if the selection isn't empty.
+    fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor?;
+        (anchor != self.cursor).then(|| anchor.min(self.cursor)..anchor.max(self.cursor))
+    }
+
+    fn selected_text(&self) -> Option<&str> {
+        self.selection().map(|range| &self.text[range])
+    }
+
+    /// Inserts `text` at the cursor, replacing the selection.
+    fn insert(&mut self, text: &str) {
+        self.delete_selection();
+        self.text.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        let selection = self.selection();
+        self.anchor = None;
+        match selection {
+            Some(range) => {
+                self.cursor = range.start;
+                self.text.replace_range(range, "");
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn delete_backward(&mut self) {
+        if !self.delete_selection() && self.cursor > 0 {
+            let start = self.prev_boundary(self.cursor);
+            self.text.replace_range(start..self.cursor, "");
+            self.cursor = start;
+        }
+    }
+
+    fn delete_forward(&mut self) {
+        if !self.delete_selection() && self.cursor < self.text.len() {
+            let end = self.next_boundary(self.cursor);
+            self.text.replace_range(self.cursor..end, "");
+        }
+    }
+
+    /// Moves the cursor, extending the selection if `select` is true.
+    fn move_to(&mut self, position: usize, select: bool) {
+        if select {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = position;
+    }
+
+    pub(crate) fn select_all(&mut self) {
+        self.anchor = Some(0);
+        self.cursor = self.text.len();
+    }
+
+    fn prev_boundary(&self, position: usize) -> usize {
+        self.text[..position]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(index, _)| index)
+    }
+
+    fn next_boundary(&self, position: usize) -> usize {
+        self.text[position..]
+            .chars()
+            .next()
+            .map_or(position, |char| position + char.len_utf8())
+    }
+
+    /// The start of the word before `position`, skipping the spaces and
+    /// punctuation in between.
+    fn prev_word(&self, position: usize) -> usize {
+        let before = self.text[..position].trim_end_matches(|char| !is_word_char(char));
+        before.trim_end_matches(is_word_char).len()
+    }
+
+    /// The end of the word after `position`, skipping the spaces and
+    /// punctuation in between.
+    fn next_word(&self, position: usize) -> usize {
+        let after = self.text[position..].trim_start_matches(|char| !is_word_char(char));
+        self.text.len() - after.trim_start_matches(is_word_char).len()
+    }
+
+    fn line_start(&self, position: usize) -> usize {
+        self.text[..position]
+            .rfind('\n')
+            .map_or(0, |index| index + 1)
+    }
+
+    fn line_end(&self, position: usize) -> usize {
+        self.text[position..]
+            .find('\n')
+            .map_or(self.text.len(), |index| position + index)
+    }
+
+    /// The position in the previous or next line with the same column,
+    /// counted in characters.
+    fn vertical(&self, position: usize, up: bool) -> usize {
+        let start = self.line_start(position);
+        let column = self.text[start..position].chars().count();
+        let target_start = if up {
+            if start == 0 {
+                return 0;
+            }
+            self.line_start(start - 1)
+        } else {
+            let end = self.line_end(position);
+            if end == self.text.len() {
+                return end;
+            }
+            end + 1
+        };
+        let target_end = self.line_end(target_start);
+        self.text[target_start..target_end]
+            .char_indices()
+            .nth(column)
+            .map_or(target_end, |(index, _)| target_start + index)
+    }
+}
+
+/// Whether `char` belongs to a word, for moving by words and selecting them.
+pub(crate) fn is_word_char(char: char) -> bool {
+    char.is_alphanumeric() || char == '_'
+}
+
+/// Creates an empty [`TextInput`] showing `placeholder` while it is empty.
+pub(crate) fn text_input(placeholder: &str) -> impl Bundle {
+    text_input_with(placeholder, "")
+}
+
+/// Creates a [`TextInput`] holding `text`, showing `placeholder` once it is
+/// emptied.
+pub(crate) fn text_input_with(placeholder: &str, text: &str) -> impl Bundle {
+    (
+        TextInput::new(text),
+        Placeholder(placeholder.to_string()),
+        Node {
+            flex_grow: 1.,
+            min_width: px(0),
+            max_height: px(LINE_HEIGHT * 6. + 20.),
+            padding: UiRect::axes(px(16), px(10)),
+            overflow: Overflow::scroll_y(),
+            border_radius: BorderRadius::all(px(22)),
+            ..default()
+        },
+        BackgroundColor(BUBBLE_BACKGROUND_COLOR),
+    )
+}
+
+fn setup_text_input(add: On<Add, TextInput>, mut commands: Commands) {
+    let entity = add.entity;
+    let text_font = TextFont {
+        font_size: FONT_SIZE,
+        ..default()
+    };
+
+    let preedit = commands
+        .spawn((
+            TextSpan::default(),
+            text_font.clone(),
+            TextColor(TEXT_COLOR),
+            Underline,
+        ))
+        .id();
+    let selection = commands
+        .spawn((
+            TextSpan::default(),
+            text_font.clone(),
+            TextColor(TEXT_COLOR),
+            TextBackgroundColor(SELECTION_COLOR),
+        ))
+        .id();
+    let after = commands
+        .spawn((
+            TextSpan::default(),
+            text_font.clone(),
+            TextColor(TEXT_COLOR),
+        ))
+        .id();
+    let text = commands
+        .spawn((
+            Text::default(),
+            text_font,
+            TextColor(TEXT_COLOR),
+            LineHeight::Px(LINE_HEIGHT),
+            Pickable::IGNORE,
+        ))
+        .add_children(&[preedit, selection, after])
+        .id();
+    let caret = commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: px(CARET_WIDTH),
+                height: px(LINE_HEIGHT),
+                ..default()
+            },
+            BackgroundColor(TEXT_COLOR),
+            Visibility::Hidden,
+            Pickable::IGNORE,
+        ))
+        .id();
+    let content = commands
+        .spawn((
+            Node {
+                flex_grow: 1.,
+                min_height: px(LINE_HEIGHT),
+                ..default()
+            },
+            Pickable::IGNORE,
+        ))
+        .add_children(&[text, caret])
+        .id();
+
+    commands
+        .entity(entity)
+        .add_child(content)
+        .insert(TextInputParts {
+            text,
+            preedit,
+            selection,
+            after,
+            caret,
+        })
+        .observe(on_press)
+        .observe(on_drag);
+}
+
+fn is_shortcut_pressed(keys: &ButtonInput<KeyCode>) -> bool {
+    keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ])
+}
+
+fn on_keyboard_input(
+    mut keyboard_input: On<FocusedInput<KeyboardInput>>,
+    mut text_inputs: Query<&mut TextInput>,
+    single_lines: Query<(), With<SingleLine>>,
+    masked: Query<(), With<Masked>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut commands: Commands,
+) {
+    let entity = keyboard_input.focused_entity;
+    let Ok(mut text_input) = text_inputs.get_mut(entity) else {
+        return;
+    };
+    let input = &keyboard_input.input;
+    // Keys go to the input method while it composes.
+    if input.state != ButtonState::Pressed || !text_input.preedit.is_empty() {
+        return;
+    }
+
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let shortcut = is_shortcut_pressed(&keys);
+    let cursor = text_input.cursor;
+    let is_single_line = single_lines.contains(entity);
+    let is_masked = masked.contains(entity);
+
+    match &input.logical_key {
+        Key::Enter if shift && !is_single_line => text_input.insert("\n"),
+        Key::Enter => {
+            commands.trigger(TextInputSubmit { entity, shift });
+        }
+        Key::Escape => {
+            commands.trigger(TextInputCancel { entity });
+        }
+        Key::Backspace => text_input.delete_backward(),
+        Key::Delete => text_input.delete_forward(),
+        Key::ArrowLeft => {
+            // Masked inputs are one word, so their words aren't given away.
+            let position = match text_input.selection() {
+                Some(range) if !shift => range.start,
+                _ if shortcut && is_masked => 0,
+                _ if shortcut => text_input.prev_word(cursor),
+                _ => text_input.prev_boundary(cursor),
+            };
+            text_input.move_to(position, shift);
+        }
+        Key::ArrowRight => {
+            let position = match text_input.selection() {
+                Some(range) if !shift => range.end,
+                _ if shortcut && is_masked => text_input.text.len(),
+                _ if shortcut => text_input.next_word(cursor),
+                _ => text_input.next_boundary(cursor),
+            };
+            text_input.move_to(position, shift);
+        }
+        Key::ArrowUp => {
+            let position = text_input.vertical(cursor, true);
+            text_input.move_to(position, shift);
+        }
+        Key::ArrowDown => {
+            let position = text_input.vertical(cursor, false);
+            text_input.move_to(position, shift);
+        }
+        Key::Home => {
+            let position = text_input.line_start(cursor);
+            text_input.move_to(position, shift);
+        }
+        Key::End => {
+            let position = text_input.line_end(cursor);
+            text_input.move_to(position, shift);
+        }
+        Key::Character(char) if shortcut => match char.to_lowercase().as_str() {
+            "a" => text_input.select_all(),
+            "c" | "x" if is_masked => {}
+            "c" => {
+                if let Some(text) = text_input.selected_text() {
+                    set_clipboard_text(text);
+                }
+            }
+            "x" => {
+                if let Some(text) = text_input.selected_text() {
+                    set_clipboard_text(text);
+                    text_input.delete_selection();
+                }
+            }
+            "v" => {
+                if let Some(text) = clipboard_text() {
+                    let text = text.replace("\r\n", "\n");
+                    if is_single_line {
+                        text_input.insert(&text.replace('\n', " "));
+                    } else {
+                        text_input.insert(&text);
+                    }
+                }
+            }
+            _ => return,
+        },
+        Key::Character(_) | Key::Space if !shortcut => {
+            let Some(text) = &input.text else {
+                return;
+            };
+            if text.chars().any(char::is_control) {
+                return;
+            }
+            text_input.insert(text);
+        }
+        // Let other keys, like Tab, reach the window.
+        _ => return,
+    }
+
+    keyboard_input.propagate(false);
+}
+
+fn clipboard_text() -> Option<String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .inspect_err(|err| log::warn!("Failed to read the clipboard: {err}"))
+        .ok()
+}
+
+pub(crate) fn set_clipboard_text(text: &str) {
+    if let Err(err) =
+        arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text.to_string()))
+    {
+        log::warn!("Failed to write the clipboard: {err}");
+    }
+}
+
+/// Finds the byte offset in `text` closest to `point`, given in physical
+/// pixels relative to the top left corner of the text node.
+pub(crate) fn hit_test(text: &str, layout: &TextLayoutInfo, point: Vec2) -> usize {
+    // Glyphs report their byte range within their line.
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+        .collect();
+
+    // Pick the visual line under the point, or the closest one.
+    let Some(line) = layout.run_geometry.iter().min_by(|a, b| {
+        let distance = |rect: &Rect| (rect.center().y - point.y).abs();
+        distance(&a.bounds).total_cmp(&distance(&b.bounds))
+    }) else {
+        return text.len();
+    };
+    let line = line.bounds;
+
+    let mut glyphs: Vec<_> = layout
+        .glyphs
+        .iter()
+        .filter(|glyph| line.min.y <= glyph.position.y && glyph.position.y <= line.max.y)
+        .collect();
+    glyphs.sort_by(|a, b| a.position.x.total_cmp(&b.position.x));
+
+    let start = |glyph: &&bevy::text::PositionedGlyph| {
+        line_starts.get(glyph.line_index).copied().unwrap_or(0) + glyph.byte_index
+    };
+    match glyphs.iter().find(|glyph| point.x < glyph.position.x) {
+        Some(glyph) => start(glyph),
+        None => glyphs
+            .last()
+            .map_or(text.len(), |glyph| start(glyph) + glyph.byte_length),
+    }
+    .min(text.len())
+}
+
+/// The byte offset under the pointer in a [`TextInput`].
+fn pointer_position(
+    entity: Entity,
+    pointer: Vec2,
+    text_inputs: &Query<(&mut TextInput, &TextInputParts, Has<Masked>)>,
+    text_nodes: &Query<(&ComputedNode, &UiGlobalTransform, &TextLayoutInfo)>,
+) -> Option<usize> {
+    let (text_input, parts, is_masked) = text_inputs.get(entity).ok()?;
+    // Glyph offsets don't match the text while the preedit is shown.
+    if !text_input.preedit.is_empty() {
+        return None;
+    }
+    let (computed, transform, layout) = text_nodes.get(parts.text).ok()?;
+    let normalized =
+        computed.normalize_point(*transform, pointer / computed.inverse_scale_factor())?;
+    let point = (normalized + 0.5) * computed.size();
+    if !is_masked {
+        return Some(hit_test(&text_input.text, layout, point));
+    }
+
+    // Hit test the dots, then find the character under the same dot.
+    let text = &text_input.text;
+    let dots = mask(text);
+    let index = hit_test(&dots, layout, point) / MASK_CHAR.len_utf8();
+    Some(
+        text.char_indices()
+            .nth(index)
+            .map_or(text.len(), |(i, _)| i),
+    )
+}
+
+fn mask(text: &str) -> String {
+    text.chars().map(|_| MASK_CHAR).collect()
+}
+
+fn on_press(
+    press: On<Pointer<Press>>,
+    mut text_inputs: Query<(&mut TextInput, &TextInputParts, Has<Masked>)>,
+    text_nodes: Query<(&ComputedNode, &UiGlobalTransform, &TextLayoutInfo)>,
+    keys: Res<ButtonInput<KeyCode>>,
+) {
+    if press.button != PointerButton::Primary {
+        return;
+    }
+    let Some(position) = pointer_position(
+        press.entity,
+        press.pointer_location.position,
+        &text_inputs,
+        &text_nodes,
+    ) else {
+        return;
+    };
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if let Ok((mut text_input, ..)) = text_inputs.get_mut(press.entity) {
+        text_input.move_to(position, shift);
+    }
+}
+
+fn on_drag(
+    drag: On<Pointer<Drag>>,
+    mut text_inputs: Query<(&mut TextInput, &TextInputParts, Has<Masked>)>,
+    text_nodes: Query<(&ComputedNode, &UiGlobalTransform, &TextLayoutInfo)>,
+) {
+    if drag.button != PointerButton::Primary {
+        return;
+    }
+    let Some(position) = pointer_position(
+        drag.entity,
+        drag.pointer_location.position,
+        &text_inputs,
+        &text_nodes,
+    ) else {
+        return;
+    };
+    if let Ok((mut text_input, ..)) = text_inputs.get_mut(drag.entity) {
+        text_input.move_to(position, true);
+    }
+}
+
+/// Applies input method events to the focused [`TextInput`].
+fn handle_ime(
+    mut ime_events: MessageReader<Ime>,
+    mut text_inputs: Query<&mut TextInput>,
+    input_focus: Res<InputFocus>,
+) {
+    for ime in ime_events.read() {
+        match ime {
+            Ime::Preedit { value, .. } => {
+                if let Some(mut text_input) = input_focus
+                    .get()
+                    .and_then(|entity| text_inputs.get_mut(entity).ok())
+                {
+                    text_input.preedit = value.clone();
+                }
+            }
+            Ime::Commit { value, .. } => {
+                if let Some(mut text_input) = input_focus
+                    .get()
+                    .and_then(|entity| text_inputs.get_mut(entity).ok())
+                {
+                    text_input.preedit.clear();
+                    text_input.insert(value);
+                }
+            }
+            Ime::Disabled { .. } => {
+                for mut text_input in &mut text_inputs {
+                    if !text_input.preedit.is_empty() {
+                        text_input.preedit.clear();
+                    }
+                }
+            }
+            Ime::Enabled { .. } => {}
+        }
+    }
+}
+
+/// Enables the input method while a [`TextInput`] has focus.
+fn update_ime_enabled(
+    text_inputs: Query<(), With<TextInput>>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    input_focus: Res<InputFocus>,
+) {
+    let Ok(mut window) = windows.single_mut() else {
+        return;
+    };
+    let ime_enabled = input_focus
+        .get()
+        .is_some_and(|entity| text_inputs.contains(entity));
+    if window.ime_enabled != ime_enabled {
+        window.ime_enabled = ime_enabled;
+    }
+}
+
+/// Splits the content into the spans around the selection, or around the
+/// preedit while composing.
+fn update_text_spans(
+    text_inputs: Query<
+        (&TextInput, &Placeholder, &TextInputParts, Has<Masked>),
+        Changed<TextInput>,
+    >,
+    mut texts: Query<&mut Text>,
+    mut spans: Query<(&mut TextSpan, &mut TextColor)>,
+) {
+    for (text_input, placeholder, parts, is_masked) in &text_inputs {
+        let text = &text_input.text;
+        let shown = |part: &str| {
+            if is_masked {
+                mask(part)
+            } else {
+                part.to_string()
+            }
+        };
+        let selection = match text_input.selection() {
+            Some(selection) if text_input.preedit.is_empty() => selection,
+            _ => text_input.cursor..text_input.cursor,
+        };
+
+        if let Ok(mut before) = texts.get_mut(parts.text) {
+            before.0 = shown(&text[..selection.start]);
+        }
+        if let Ok((mut span, _)) = spans.get_mut(parts.preedit) {
+            span.0 = shown(&text_input.preedit);
+        }
+        if let Ok((mut span, _)) = spans.get_mut(parts.selection) {
+            span.0 = shown(&text[selection.clone()]);
+        }
+        if let Ok((mut span, mut color)) = spans.get_mut(parts.after) {
+            if text.is_empty() && text_input.preedit.is_empty() {
+                span.0 = placeholder.0.clone();
+                color.0 = NOTE_TEXT_COLOR;
+            } else {
+                span.0 = shown(&text[selection.end..]);
+                color.0 = TEXT_COLOR;
+            }
+        }
+    }
+}
+
+/// Places the caret at the cursor of the focused input, scrolls it into view
+/// and moves the input method's candidate window below it.
+fn update_caret(
+    mut text_inputs: Query<(
+        Entity,
+        &TextInput,
+        &TextInputParts,
+        &ComputedNode,
+        &mut ScrollPosition,
+    )>,
+    text_nodes: Query<(
+        &TextLayoutInfo,
+        &ComputedTextBlock,
+        &ComputedNode,
+        &UiGlobalTransform,
+    )>,
+    mut carets: Query<(&mut Node, &mut Visibility)>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    input_focus: Res<InputFocus>,
+) {
+    for (entity, text_input, parts, computed, mut scroll_position) in &mut text_inputs {
+        let Ok((mut caret_node, mut visibility)) = carets.get_mut(parts.caret) else {
+            continue;
+        };
+        let is_focused = input_focus.get() == Some(entity);
+        visibility.set_if_neq(if is_focused {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+        if !is_focused {
+            continue;
+        }
+        let Ok((layout, block, text_computed, text_transform)) = text_nodes.get(parts.text) else {
+            continue;
+        };
+
+        // While composing, the caret follows the preedit. Otherwise the cursor
+        // sits at one end of the selection span: after it when the selection
+        // was extended forwards, before it otherwise.
+        let mut spans_before_cursor = vec![parts.text];
+        let mut text_before = text_input.text[..text_input.cursor].to_string();
+        if !text_input.preedit.is_empty() {
+            spans_before_cursor.push(parts.preedit);
+            text_before += &text_input.preedit;
+        } else if text_input
+            .anchor
+            .is_some_and(|anchor| anchor < text_input.cursor)
+        {
+            spans_before_cursor.push(parts.selection);
+        }
+        let runs = layout.run_geometry.iter().filter(|run| {
+            block
+                .entities()
+                .get(run.span_index)
+                .is_some_and(|span| spans_before_cursor.contains(&span.entity))
+        });
+        let scale = layout.scale_factor;
+        let position = caret_position(
+            &text_before,
+            runs.map(|run| run.bounds),
+            LINE_HEIGHT * scale,
+        ) / scale;
+
+        caret_node.left = px(position.x);
+        caret_node.top = px(position.y);
+
+        // Keep the caret inside the visible part of the input.
+        let insets = computed.padding.min_inset.y
+            + computed.padding.max_inset.y
+            + computed.border.min_inset.y
+            + computed.border.max_inset.y;
+        let visible_height = (computed.size().y - insets) * computed.inverse_scale_factor();
+        if position.y < scroll_position.y {
+            scroll_position.y = position.y;
+        } else if position.y + LINE_HEIGHT > scroll_position.y + visible_height {
+            scroll_position.y = position.y + LINE_HEIGHT - visible_height;
+        }
+
+        if let Ok(mut window) = windows.single_mut() {
+            let text_top_left = (text_transform.translation - text_computed.size() / 2.)
+                * text_computed.inverse_scale_factor();
+            let ime_position = text_top_left + position + Vec2::new(0., LINE_HEIGHT);
+            if window.ime_position != ime_position {
+                window.ime_position = ime_position;
+            }
+        }
+    }
+}
+
+/// The top left corner of a caret placed after `text_before`, whose glyphs
+/// were laid out into `runs`.
+fn caret_position(text_before: &str, runs: impl Iterator<Item = Rect>, line_height: f32) -> Vec2 {
+    let last_run = runs.max_by(|a, b| {
+        a.min
+            .y
+            .total_cmp(&b.min.y)
+            .then(a.max.x.total_cmp(&b.max.x))
+    });
+    // Line breaks produce no glyphs, so count the ones after the last glyph.
+    let trailing_breaks = text_before
+        .chars()
+        .rev()
+        .take_while(|char| *char == '\n')
+        .count() as f32;
+    match last_run {
+        Some(run) if trailing_breaks == 0. => Vec2::new(run.max.x, run.min.y),
+        Some(run) => Vec2::new(0., run.min.y + trailing_breaks * line_height),
+        None => Vec2::new(0., trailing_breaks * line_height),
+    }
+}
+
+pub(crate) struct UiInputPlugin;
+
+impl Plugin for UiInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(setup_text_input)
+            .add_observer(on_keyboard_input)
+            .add_systems(Update, (handle_ime, update_ime_enabled))
+            .add_systems(
+                PostUpdate,
+                update_text_spans.before(bevy::ui::UiSystems::Prepare),
+            )
+            .add_systems(
+                PostUpdate,
+                update_caret.after(bevy::ui::UiSystems::PostLayout),
+            );
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use bevy::text::{GlyphAtlasInfo, GlyphAtlasLocation, PositionedGlyph, RunGeometry};
+
+    use super::*;
+
+    /// Glyph width and line height of [`monospace_layout`].
+    pub(crate) const GLYPH_SIZE: Vec2 = Vec2::new(10., 20.);
+
+    /// The layout of `text` in a font whose glyphs are all [`GLYPH_SIZE`],
+    /// without wrapping.
+    pub(crate) fn monospace_layout(text: &str) -> TextLayoutInfo {
+        let mut layout = TextLayoutInfo {
+            scale_factor: 1.,
+            ..default()
+        };
+        for (line_index, line) in text.split('\n').enumerate() {
+            let top = line_index as f32 * GLYPH_SIZE.y;
+            for (column, (byte_index, char)) in line.char_indices().enumerate() {
+                layout.glyphs.push(PositionedGlyph {
+                    position: Vec2::new(
+                        (column as f32 + 0.5) * GLYPH_SIZE.x,
+                        top + GLYPH_SIZE.y / 2.,
+                    ),
+                    size: GLYPH_SIZE,
+                    atlas_info: GlyphAtlasInfo {
+                        texture: default(),
+                        texture_atlas: default(),
+                        location: GlyphAtlasLocation {
+                            glyph_index: 0,
+                            offset: IVec2::ZERO,
+                        },
+                    },
+                    span_index: 0,
+                    line_index,
+                    byte_index,
+                    byte_length: char.len_utf8(),
+                });
+            }
+            let width = line.chars().count() as f32 * GLYPH_SIZE.x;
+            layout.run_geometry.push(RunGeometry {
+                bounds: Rect::new(0., top, width, top + GLYPH_SIZE.y),
+                ..default()
+            });
+        }
+        layout
+    }
+
+    /// A field holding `text` with the cursor at `cursor`.
+    fn field(text: &str, cursor: usize) -> TextInput {
+        let mut text_input = TextInput::new(text);
+        text_input.move_to(cursor, false);
+        text_input
+    }
+
+    #[test]
+    fn inserts_and_deletes_whole_characters() {
+        let mut text_input = field("a你b", 1);
+        text_input.insert("xy");
+        assert_eq!((text_input.text(), text_input.cursor), ("axy你b", 3));
+
+        text_input.move_to(3 + "你".len(), false);
+        text_input.delete_backward();
+        assert_eq!((text_input.text(), text_input.cursor), ("axyb", 3));
+        text_input.delete_forward();
+        text_input.delete_forward();
+        assert_eq!((text_input.text(), text_input.cursor), ("axy", 3));
+
+        text_input.move_to(0, false);
+        text_input.delete_backward();
+        assert_eq!((text_input.text(), text_input.cursor), ("axy", 0));
+    }
+
+    #[test]
+    fn replaces_and_deletes_the_selection() {
+        let mut text_input = field("hello world", 0);
+        text_input.move_to(5, true);
+        assert_eq!(text_input.selected_text(), Some("hello"));
+        text_input.insert("bye");
+        assert_eq!((text_input.text(), text_input.cursor), ("bye world", 3));
+        assert_eq!(text_input.selection(), None);
+
+        // Selecting backwards keeps the anchor at the end.
+        text_input.move_to(9, false);
+        text_input.move_to(4, true);
+        text_input.move_to(3, true);
+        assert_eq!(text_input.selection(), Some(3..9));
+        text_input.delete_forward();
+        assert_eq!((text_input.text(), text_input.cursor), ("bye", 3));
+
+        text_input.select_all();
+        text_input.delete_backward();
+        assert_eq!(text_input.text(), "");
+    }
+
+    #[test]
+    fn moves_by_words() {
+        let text_input = field("let foo_bar = 你好, x;", 0);
+        let text = text_input.text();
+        let after_bar = text.find(" =").unwrap();
+        let after_hello = text.find(',').unwrap();
+
+        assert_eq!(text_input.next_word(0), 3);
+        assert_eq!(text_input.next_word(3), after_bar);
+        assert_eq!(text_input.next_word(after_bar), after_hello);
+        assert_eq!(text_input.next_word(text.len() - 1), text.len());
+        assert_eq!(text_input.prev_word(after_hello), text.find('你').unwrap());
+        assert_eq!(text_input.prev_word(after_bar + 2), 4);
+        assert_eq!(text_input.prev_word(2), 0);
+    }
+
+    #[test]
+    fn moves_between_lines_by_column() {
+        let text_input = field("abcd\nx\n你好吗", 0);
+        let third_line = "abcd\nx\n".len();
+
+        assert_eq!(text_input.vertical(3, false), 6);
+        assert_eq!(text_input.vertical(6, false), third_line + "你".len());
+        assert_eq!(text_input.vertical(third_line + "你好".len(), true), 6);
+        assert_eq!(text_input.vertical(5, true), 0);
+        assert_eq!(text_input.vertical(2, true), 0);
+        assert_eq!(
+            text_input.vertical(third_line, false),
+            text_input.text().len()
+        );
+        assert_eq!((text_input.line_start(6), text_input.line_end(6)), (5, 6));
+    }
+
+    #[test]
+    fn finds_the_offset_under_a_point() {
+        let text = "ab\n你好";
+        let layout = monospace_layout(text);
+        let at = |x: f32, y: f32| hit_test(text, &layout, Vec2::new(x, y));
+
+        assert_eq!(at(0., 10.), 0);
+        assert_eq!(at(14., 10.), 1);
+        assert_eq!(at(16., 10.), 2);
+        assert_eq!(at(100., 10.), 2);
+        assert_eq!(at(12., 30.), "ab\n你".len());
+        // Below the last line.
+        assert_eq!(at(100., 100.), text.len());
+        assert_eq!(hit_test("", &TextLayoutInfo::default(), Vec2::ZERO), 0);
+    }
+
+    #[test]
+    fn places_the_caret_after_the_text_before_it() {
+        let layout = monospace_layout("ab\ncd");
+        let runs = || layout.run_geometry.iter().map(|run| run.bounds);
+        assert_eq!(caret_position("ab\ncd", runs(), 20.), Vec2::new(20., 20.));
+        assert_eq!(
+            caret_position("ab\ncd\n\n", runs(), 20.),
+            Vec2::new(0., 60.)
+        );
+        assert_eq!(
+            caret_position("\n", std::iter::empty(), 20.),
+            Vec2::new(0., 20.)
+        );
+    }
+
+    fn app() -> (App, Entity, Entity) {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, UiInputPlugin))
+            .add_message::<Ime>()
+            .init_resource::<InputFocus>();
+        let window = app
+            .world_mut()
+            .spawn((Window::default(), PrimaryWindow))
+            .id();
+        let input = app
+            .world_mut()
+            .spawn(text_input("Message"))
+            .insert(TextInput::new("ab"))
+            .id();
+        app.world_mut().resource_mut::<InputFocus>().set(input);
+        app.update();
+
+        // Without the UI plugins nothing lays the text out.
+        let text = app.world().get::<TextInputParts>(input).unwrap().text;
+        app.world_mut().entity_mut(text).insert(TextLayoutInfo {
+            scale_factor: 1.,
+            ..default()
+        });
+        app.world_mut()
+            .get_mut::<TextInput>(input)
+            .unwrap()
+            .move_to(1, false);
+        (app, window, input)
+    }
+
+    fn text_input_of(app: &App, input: Entity) -> &TextInput {
+        app.world().get::<TextInput>(input).unwrap()
+    }
+
+    #[test]
+    fn shows_the_preedit_until_it_is_committed() {
+        let (mut app, window, input) = app();
+        app.world_mut().write_message(Ime::Enabled { window });
+        app.world_mut().write_message(Ime::Preedit {
+            window,
+            value: "ni".to_string(),
+            cursor: Some((2, 2)),
+        });
+        app.update();
+
+        let text_input = text_input_of(&app, input);
+        assert_eq!(text_input.text(), "ab");
+        assert_eq!(text_input.preedit, "ni");
+        let parts = app.world().get::<TextInputParts>(input).unwrap();
+        assert_eq!(app.world().get::<Text>(parts.text).unwrap().0, "a");
+        assert_eq!(app.world().get::<TextSpan>(parts.preedit).unwrap().0, "ni");
+        assert_eq!(app.world().get::<TextSpan>(parts.after).unwrap().0, "b");
+
+        app.world_mut().write_message(Ime::Commit {
+            window,
+            value: "你".to_string(),
+        });
+        app.update();
+
+        let text_input = text_input_of(&app, input);
+        assert_eq!(text_input.text(), "a你b");
+        assert_eq!(text_input.cursor, "a你".len());
+        assert!(text_input.preedit.is_empty());
+        let parts = app.world().get::<TextInputParts>(input).unwrap();
+        assert_eq!(app.world().get::<TextSpan>(parts.preedit).unwrap().0, "");
+    }
+
+    #[test]
+    fn masks_the_preedit_of_masked_inputs() {
+        let (mut app, window, input) = app();
+        app.world_mut().entity_mut(input).insert(Masked);
+        app.world_mut().write_message(Ime::Preedit {
+            window,
+            value: "ni".to_string(),
+            cursor: Some((2, 2)),
+        });
+        app.update();
+
+        let parts = app.world().get::<TextInputParts>(input).unwrap();
+        assert_eq!(app.world().get::<Text>(parts.text).unwrap().0, "•");
+        assert_eq!(app.world().get::<TextSpan>(parts.preedit).unwrap().0, "••");
+        assert_eq!(app.world().get::<TextSpan>(parts.after).unwrap().0, "•");
+    }
+
+    #[test]
+    fn enables_the_input_method_while_focused() {
+        let (mut app, window, _input) = app();
+        app.update();
+        let window_of = |app: &App| app.world().get::<Window>(window).unwrap().clone();
+        assert!(window_of(&app).ime_enabled);
+        // Below the caret, which sits on the first line.
+        assert_eq!(window_of(&app).ime_position, Vec2::new(0., LINE_HEIGHT));
+
+        app.world_mut().resource_mut::<InputFocus>().clear();
+        app.update();
+        assert!(!window_of(&app).ime_enabled);
+    }
+}
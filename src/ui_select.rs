@@ -0,0 +1,525 @@
+use std::{collections::HashMap, ops::Range, time::Duration};
+
+use bevy::{
+    input::{
+        ButtonState,
+        keyboard::{Key, KeyboardInput},
+    },
+    input_focus::FocusedInput,
+    prelude::*,
+    text::TextLayoutInfo,
+    ui::{UiGlobalTransform, UiStack, widget::TextUiReader},
+};
+
+use crate::ui_input::{hit_test, is_word_char, set_clipboard_text};
+
+const HIGHLIGHT_COLOR: Color = Color::Srgba(Srgba::new(0.2627451, 0.4745098, 0.8509804, 0.45));
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(500);
+/// How far the pointer may move between the two presses of a double-click,
+/// in logical pixels.
+const DOUBLE_CLICK_DISTANCE: f32 = 4.;
+
+/// A [`Text`] whose content can be selected with the mouse and copied.
+#[derive(Component, Default)]
+pub(crate) struct SelectableText;
+
+/// A byte offset in the content of a [`SelectableText`], spans included.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct SelectionPoint {
+    text: Entity,
+    offset: usize,
+}
+
+/// The selection across all [`SelectableText`]s.
+#[derive(Resource, Default)]
+struct TextSelection {
+    /// Where the selection started.
+    anchor: Option<SelectionPoint>,
+    /// Where the selection ends, following the pointer while dragging.
+    focus: Option<SelectionPoint>,
+    is_dragging: bool,
+}
+
+/// Marks the nodes drawn over selected text.
+#[derive(Component)]
+struct SelectionHighlight;
+
+/// The last primary press on a [`SelectableText`], to detect double-clicks.
+#[derive(Default)]
+struct LastPress {
+    time: Duration,
+    text: Option<Entity>,
+    position: Vec2,
+}
+
+/// The content of `text` with all of its spans.
+pub(crate) fn full_text(reader: &mut TextUiReader, text: Entity) -> String {
+    reader.iter(text).map(|(_, _, span, ..)| span).collect()
+}
+
+/// The range of the word around `offset`, or of the character there if it
+/// isn't part of a word.
+fn word_range(text: &str, offset: usize) -> Range<usize> {
+    let Some(char) = text[offset..].chars().next() else {
+        return offset..offset;
+    };
+    if !is_word_char(char) {
+        return offset..offset + char.len_utf8();
+    }
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|(_, char)| is_word_char(*char))
+        .last()
+        .map_or(offset, |(index, _)| index);
+    let end = text[offset..]
+        .char_indices()
+        .find(|(_, char)| !is_word_char(*char))
+        .map_or(text.len(), |(index, _)| offset + index);
+    start..end
+}
+
+/// The order of the UI nodes from back to front, which follows the order
+/// they appear in for nodes without a z-index.
+pub(crate) fn document_order(ui_stack: &UiStack) -> HashMap<Entity, usize> {
+    ui_stack
+        .uinodes
+        .iter()
+        .enumerate()
+        .map(|(index, entity)| (*entity, index))
+        .collect()
+}
+
+impl TextSelection {
+    fn clear(&mut self) {
+        self.anchor = None;
+        self.focus = None;
+    }
+
+    /// The selected range of every text in the selection, in document order,
+    /// with the content `full_text` gives for it.
+    fn ranges(
+        &self,
+        order: &HashMap<Entity, usize>,
+        texts: impl Iterator<Item = Entity>,
+        mut full_text: impl FnMut(Entity) -> String,
+    ) -> Vec<(Entity, String, Range<usize>)> {
+        let (Some(anchor), Some(focus)) = (self.anchor, self.focus) else {
+            return Vec::new();
+        };
+        let (Some(&anchor_index), Some(&focus_index)) =
+            (order.get(&anchor.text), order.get(&focus.text))
+        else {
+            return Vec::new();
+        };
+        let (start, end) = if (anchor_index, anchor.offset) <= (focus_index, focus.offset) {
+            (anchor, focus)
+        } else {
+            (focus, anchor)
+        };
+        let (start_index, end_index) =
+            (anchor_index.min(focus_index), anchor_index.max(focus_index));
+
+        let mut texts: Vec<_> = texts
+            .filter_map(|text| Some((*order.get(&text)?, text)))
+            .filter(|(index, _)| (start_index..=end_index).contains(index))
+            .collect();
+        texts.sort();
+
+        texts
+            .into_iter()
+            .filter_map(|(_, text)| {
+                let content = full_text(text);
+                let range_start = if text == start.text { start.offset } else { 0 };
+                let range_end = if text == end.text {
+                    end.offset
+                } else {
+                    content.len()
+                };
+                let range = range_start.min(content.len())..range_end.min(content.len());
+                (!range.is_empty()).then_some((text, content, range))
+            })
+            .collect()
+    }
+}
+
+/// The byte offset in `text` closest to `position`, given in logical pixels.
+fn text_offset(
+    text: Entity,
+    position: Vec2,
+    nodes: &Query<(&ComputedNode, &UiGlobalTransform, &TextLayoutInfo), With<SelectableText>>,
+    reader: &mut TextUiReader,
+) -> Option<usize> {
+    let (computed, transform, layout) = nodes.get(text).ok()?;
+    let top_left = transform.translation - computed.size() / 2.;
+    let point = position / computed.inverse_scale_factor() - top_left;
+    Some(hit_test(&full_text(reader, text), layout, point))
+}
+
+fn on_press(
+    press: On<Pointer<Press>>,
+    mut selection: ResMut<TextSelection>,
+    nodes: Query<(&ComputedNode, &UiGlobalTransform, &TextLayoutInfo), With<SelectableText>>,
+    mut reader: TextUiReader,
+    keys: Res<ButtonInput<KeyCode>>,
+    time: Res<Time<Real>>,
+    mut last_press: Local<LastPress>,
+) {
+    if press.entity != press.original_event_target() || press.button != PointerButton::Primary {
+        return;
+    }
+    let position = press.pointer_location.position;
+    let Some(offset) = text_offset(press.entity, position, &nodes, &mut reader) else {
+        selection.clear();
+        last_press.text = None;
+        return;
+    };
+    let point = SelectionPoint {
+        text: press.entity,
+        offset,
+    };
+
+    let is_double_click = last_press.text == Some(press.entity)
+        && time.elapsed() - last_press.time < DOUBLE_CLICK_TIME
+        && last_press.position.distance(position) < DOUBLE_CLICK_DISTANCE;
+    *last_press = LastPress {
+        time: time.elapsed(),
+        text: (!is_double_click).then_some(press.entity),
+        position,
+    };
+
+    if is_double_click {
+        let range = word_range(&full_text(&mut reader, press.entity), offset);
+        selection.anchor = Some(SelectionPoint {
+            offset: range.start,
+            ..point
+        });
+        selection.focus = Some(SelectionPoint {
+            offset: range.end,
+            ..point
+        });
+    } else if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+        && selection.anchor.is_some()
+    {
+        selection.focus = Some(point);
+    } else {
+        selection.anchor = Some(point);
+        selection.focus = Some(point);
+    }
+    selection.is_dragging = true;
+}
+
+/// Extends the selection to the text closest to the pointer, so that it can
+/// span several messages.
+fn on_drag(
+    drag: On<Pointer<Drag>>,
+    mut selection: ResMut<TextSelection>,
+    nodes: Query<(&ComputedNode, &UiGlobalTransform, &TextLayoutInfo), With<SelectableText>>,
+    texts: Query<(Entity, &InheritedVisibility), With<SelectableText>>,
+    mut reader: TextUiReader,
+) {
+    if !selection.is_dragging
+        || drag.entity != drag.original_event_target()
+        || drag.button != PointerButton::Primary
+    {
+        return;
+    }
+    let position = drag.pointer_location.position;
+
+    let closest = texts
+        .iter()
+        .filter(|(_, visibility)| visibility.get())
+        .filter_map(|(text, _)| {
+            let (computed, transform, _) = nodes.get(text).ok()?;
+            let point = position / computed.inverse_scale_factor();
+            let half_size = computed.size() / 2.;
+            let distance = ((point - transform.translation).abs() - half_size).max(Vec2::ZERO);
+            Some((distance.length(), text))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0));
+    if let Some((_, text)) = closest
+        && let Some(offset) = text_offset(text, position, &nodes, &mut reader)
+    {
+        selection.focus = Some(SelectionPoint { text, offset });
+    }
+}
+
+fn on_release(release: On<Pointer<Release>>, mut selection: ResMut<TextSelection>) {
+    if release.button == PointerButton::Primary && selection.is_dragging {
+        selection.is_dragging = false;
+    }
+}
+
+/// Copies the selection with Ctrl+C when no widget handled the key.
+fn on_copy(
+    keyboard_input: On<FocusedInput<KeyboardInput>>,
+    selection: Res<TextSelection>,
+    windows: Query<(), With<Window>>,
+    texts: Query<Entity, With<SelectableText>>,
+    ui_stack: Res<UiStack>,
+    mut reader: TextUiReader,
+    keys: Res<ButtonInput<KeyCode>>,
+) {
+    let input = &keyboard_input.input;
+    if !windows.contains(keyboard_input.event_target())
+        || input.state != ButtonState::Pressed
+        || !matches!(&input.logical_key, Key::Character(char) if char.eq_ignore_ascii_case("c"))
+        || !keys.any_pressed([
+            KeyCode::ControlLeft,
+            KeyCode::ControlRight,
+            KeyCode::SuperLeft,
+            KeyCode::SuperRight,
+        ])
+    {
+        return;
+    }
+
+    let ranges = selection.ranges(&document_order(&ui_stack), texts.iter(), |text| {
+        full_text(&mut reader, text)
+    });
+    if ranges.is_empty() {
+        return;
+    }
+    let text = ranges
+        .iter()
+        .map(|(_, content, range)| &content[range.clone()])
+        .collect::<Vec<_>>()
+        .join("\n");
+    set_clipboard_text(&text);
+}
+
+/// Draws a highlight over each selected line.
+#[allow(clippy::too_many_arguments)]
+fn update_highlights(
+    mut selection: ResMut<TextSelection>,
+    texts: Query<(Entity, Ref<TextLayoutInfo>), With<SelectableText>>,
+    nodes: Query<(&ComputedNode, &UiGlobalTransform)>,
+    parents: Query<&ChildOf>,
+    highlights: Query<Entity, With<SelectionHighlight>>,
+    ui_stack: Res<UiStack>,
+    mut reader: TextUiReader,
+    mut commands: Commands,
+) {
+    // Forget texts that were despawned, e.g. re-rendered while streaming.
+    let is_stale =
+        |point: Option<SelectionPoint>| point.is_some_and(|point| !texts.contains(point.text));
+    if is_stale(selection.anchor) || is_stale(selection.focus) {
+        selection.clear();
+    }
+    if !selection.is_changed() && !texts.iter().any(|(_, layout)| layout.is_changed()) {
+        return;
+    }
+
+    for highlight in &highlights {
+        commands.entity(highlight).despawn();
+    }
+
+    let ranges = selection.ranges(
+        &document_order(&ui_stack),
+        texts.iter().map(|(text, _)| text),
+        |text| full_text(&mut reader, text),
+    );
+    for (text, content, range) in ranges {
+        let Ok((_, layout)) = texts.get(text) else {
+            continue;
+        };
+        let rects = range_rects(&content, &range, &layout);
+        spawn_highlights(text, &rects, &nodes, &parents, &mut commands, || {
+            (SelectionHighlight, BackgroundColor(HIGHLIGHT_COLOR))
+        });
+    }
+}
+
+/// The rectangles covering `range` of `content`, one per line, in physical
+/// pixels from the top-left corner of the text.
+pub(crate) fn range_rects(
+    content: &str,
+    range: &Range<usize>,
+    layout: &TextLayoutInfo,
+) -> Vec<Rect> {
+    // Glyphs report their byte range within their line.
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(index, _)| index + 1))
+        .collect();
+    let mut rects: Vec<Rect> = Vec::new();
+    for glyph in &layout.glyphs {
+        let offset = line_starts.get(glyph.line_index).copied().unwrap_or(0) + glyph.byte_index;
+        if !range.contains(&offset) {
+            continue;
+        }
+        let Some(run) = layout.run_geometry.iter().find(|run| {
+            run.bounds.min.y <= glyph.position.y && glyph.position.y <= run.bounds.max.y
+        }) else {
+            continue;
+        };
+        let left = glyph.position.x - glyph.size.x / 2.;
+        let right = glyph.position.x + glyph.size.x / 2.;
+        match rects.iter_mut().find(|rect| rect.min.y == run.bounds.min.y) {
+            Some(rect) => {
+                rect.min.x = rect.min.x.min(left);
+                rect.max.x = rect.max.x.max(right);
+            }
+            None => rects.push(Rect::new(left, run.bounds.min.y, right, run.bounds.max.y)),
+        }
+    }
+    rects
+}
+
+/// Draws a node made by `highlight` over each of `rects` of `text`. The nodes
+/// are placed next to the text in its parent so that they scroll and clip
+/// with it.
+pub(crate) fn spawn_highlights<B: Bundle>(
+    text: Entity,
+    rects: &[Rect],
+    nodes: &Query<(&ComputedNode, &UiGlobalTransform)>,
+    parents: &Query<&ChildOf>,
+    commands: &mut Commands,
+    highlight: impl Fn() -> B,
+) {
+    let Ok(child_of) = parents.get(text) else {
+        return;
+    };
+    let (Ok((text_computed, text_transform)), Ok((parent_computed, parent_transform))) =
+        (nodes.get(text), nodes.get(child_of.parent()))
+    else {
+        return;
+    };
+
+    // Absolute positions are relative to the parent's padding box.
+    let text_top_left = text_transform.translation - text_computed.size() / 2.;
+    let parent_top_left = parent_transform.translation - parent_computed.size() / 2.
+        + parent_computed.border.min_inset;
+    let offset = text_top_left - parent_top_left;
+    let scale = text_computed.inverse_scale_factor();
+    for rect in rects {
+        let node = commands
+            .spawn((
+                highlight(),
+                Node {
+                    position_type: PositionType::Absolute,
+                    left: px((offset.x + rect.min.x) * scale),
+                    top: px((offset.y + rect.min.y) * scale),
+                    width: px(rect.width() * scale),
+                    height: px(rect.height() * scale),
+                    ..default()
+                },
+                Pickable::IGNORE,
+            ))
+            .id();
+        commands.entity(child_of.parent()).add_child(node);
+    }
+}
+
+pub(crate) struct UiSelectPlugin;
+
+impl Plugin for UiSelectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TextSelection>()
+            .add_observer(on_press)
+            .add_observer(on_drag)
+            .add_observer(on_release)
+            .add_observer(on_copy)
+            .add_systems(
+                PostUpdate,
+                update_highlights.after(bevy::ui::UiSystems::PostLayout),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui_input::tests::{GLYPH_SIZE, monospace_layout};
+
+    #[test]
+    fn selects_words_or_single_characters() {
+        let text = "let foo_bar = 你好, x2;";
+        let word = |offset: usize| &text[word_range(text, offset)];
+
+        assert_eq!(word(0), "let");
+        assert_eq!(word(6), "foo_bar");
+        assert_eq!(word(text.find('=').unwrap()), "=");
+        assert_eq!(word(text.find('好').unwrap()), "你好");
+        assert_eq!(word(text.find(',').unwrap()), ",");
+        assert_eq!(word(text.find("x2").unwrap() + 1), "x2");
+        assert_eq!(word_range(text, text.len()), text.len()..text.len());
+    }
+
+    #[test]
+    fn orders_the_selected_ranges_like_the_document() {
+        let mut world = World::new();
+        let [first, second, third, elsewhere] = [(); 4].map(|()| world.spawn_empty().id());
+        let order: HashMap<_, _> = [(first, 0), (second, 1), (third, 2)].into();
+        let content = |text| {
+            if text == second {
+                "second".to_string()
+            } else {
+                "text".to_string()
+            }
+        };
+        let point = |text, offset| Some(SelectionPoint { text, offset });
+
+        // Selected from the end of the third text back into the first one.
+        let selection = TextSelection {
+            anchor: point(third, 2),
+            focus: point(first, 1),
+            is_dragging: false,
+        };
+        let texts = [third, elsewhere, second, first].into_iter();
+        let ranges: Vec<_> = selection
+            .ranges(&order, texts, content)
+            .into_iter()
+            .map(|(text, _, range)| (text, range))
+            .collect();
+        assert_eq!(ranges, [(first, 1..4), (second, 0..6), (third, 0..2)]);
+
+        let selection = TextSelection {
+            anchor: point(second, 4),
+            focus: point(second, 1),
+            is_dragging: false,
+        };
+        let ranges = selection.ranges(&order, [second].into_iter(), content);
+        assert_eq!(ranges, [(second, "second".to_string(), 1..4)]);
+
+        let empty = TextSelection {
+            anchor: point(first, 2),
+            focus: point(first, 2),
+            is_dragging: false,
+        };
+        assert!(
+            empty
+                .ranges(&order, [first].into_iter(), content)
+                .is_empty()
+        );
+        let unknown = TextSelection {
+            anchor: point(elsewhere, 0),
+            focus: point(first, 2),
+            is_dragging: false,
+        };
+        assert!(
+            unknown
+                .ranges(&order, [first].into_iter(), content)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn covers_each_line_of_a_range() {
+        let text = "abc\n你好";
+        let layout = monospace_layout(text);
+        let (width, height) = (GLYPH_SIZE.x, GLYPH_SIZE.y);
+
+        assert_eq!(
+            range_rects(text, &(1..text.len()), &layout),
+            [
+                Rect::new(width, 0., 3. * width, height),
+                Rect::new(0., height, 2. * width, 2. * height),
+            ]
+        );
+        assert_eq!(
+            range_rects(text, &(4..4 + "你".len()), &layout),
+            [Rect::new(0., height, width, 2. * height)]
+        );
+        assert!(range_rects(text, &(3..4), &layout).is_empty());
+    }
+}